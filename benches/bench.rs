@@ -7,7 +7,7 @@ const DIMENSIONS: usize = 2;
 
 #[derive(Clone)]
 struct Point<const D: usize> {
-    cords: [f64; DIMENSIONS],
+    cords: [f64; D],
 }
 
 impl<const D: usize> Distance<Point<D>> for Point<D> {
@@ -28,7 +28,7 @@ impl<const D: usize> Distance<Point<D>> for Point<D> {
 impl<const D: usize> Point<D> {
     fn new_random() -> Self {
         Point {
-            cords: [(); DIMENSIONS].map(|_| fastrand::f64() * 1000.0),
+            cords: [(); D].map(|_| fastrand::f64() * 1000.0),
         }
     }
 }
@@ -58,6 +58,31 @@ fn construction(c: &mut Criterion) {
     }
 }
 
+fn construction_limited(c: &mut Criterion) {
+    let mut group = c.benchmark_group("VpTree Construction (Thread limit)");
+
+    let num_points = [1_000_000, 10_000_000];
+    let max_threads = [8];
+
+    for &points in &num_points {
+        for &threads in &max_threads {
+            group.bench_function(format!("Constructing VpTree with {} points on at most {:02} threads", points, threads),
+                |b|b.iter_batched(
+                    || {
+                        (0..points)
+                        .map(|_| Point::<DIMENSIONS>::new_random())
+                        .collect()
+                    },
+                    |data| {
+                        let _vp_tree = vp_tree::VpTree::new_parallel_with_limit(black_box(data), black_box(threads));
+                    },
+                    criterion::BatchSize::LargeInput,
+                ),
+            );
+        }
+    }
+}
+
 fn construction_index(c: &mut Criterion) {
     let mut group = c.benchmark_group("VpTree Construction (Indirect access)");
 
@@ -97,7 +122,7 @@ fn nearest_neighbor_search(c: &mut Criterion) {
 
         group.bench_function(format!("Nearest neighbor search in VpTree with {} points", points),
             |b| b.iter_batched(
-                || Point::new_random(),
+                Point::new_random,
                 |target| {
                     let _nn = vp_tree.nearest_neighbor(black_box(&target));
                 },
@@ -107,6 +132,103 @@ fn nearest_neighbor_search(c: &mut Criterion) {
     }
 }
 
+fn nearest_neighbor_along_path(c: &mut Criterion) {
+    let mut group = c.benchmark_group("VpTree Nearest Neighbor Search along a Path");
+
+    let num_points = [100_000, 1_000_000];
+    let path_len = 1000;
+
+    for &points in &num_points {
+        let random_points: Vec<Point<DIMENSIONS>> = (0..points)
+            .map(|_| Point::new_random())
+            .collect();
+
+        let vp_tree = vp_tree::VpTree::new_parallel(random_points, 16);
+
+        let path: Vec<Point<DIMENSIONS>> = (0..path_len)
+            .map(|i| {
+                let t = i as f64 / path_len as f64 * std::f64::consts::TAU;
+                Point { cords: std::array::from_fn(|d| 500.0 + (t * (d + 1) as f64).sin() * 400.0) }
+            })
+            .collect();
+
+        group.bench_function(format!("Independent nearest neighbor searches for {} path targets in VpTree with {} points", path_len, points),
+            |b| b.iter(|| {
+                let _nearest = path.iter().map(|target| vp_tree.nearest_neighbor(black_box(target))).collect::<Vec<_>>();
+            }),
+        );
+
+        group.bench_function(format!("Nearest neighbors along a path of {} targets in VpTree with {} points", path_len, points),
+            |b| b.iter(|| {
+                let _nearest = vp_tree.nearest_along_path(black_box(&path));
+            }),
+        );
+
+        group.bench_function(format!("Hinted nearest neighbor searches for {} path targets in VpTree with {} points", path_len, points),
+            |b| b.iter(|| {
+                let mut hint = vp_tree.nearest_neighbor_index(&path[0]).unwrap();
+                for target in &path {
+                    (hint, _) = vp_tree.nearest_neighbor_hinted(black_box(target), hint).unwrap();
+                }
+            }),
+        );
+    }
+}
+
+/// Position of the cell `(x, y)` along the Hilbert curve through an `n` x `n` grid, `n` being a power of two.
+fn hilbert_index(n: u64, mut x: u64, mut y: u64) -> u64 {
+    let mut index = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = ((x & s) > 0) as u64;
+        let ry = ((y & s) > 0) as u64;
+        index += s * s * ((3 * rx) ^ ry);
+        if ry == 0 {
+            if rx == 1 {
+                x = n - 1 - x;
+                y = n - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    index
+}
+
+fn k_nearest_neighbors_batch_locality_aware(c: &mut Criterion) {
+    let mut group = c.benchmark_group("VpTree K Nearest Neighbors Search for Hilbert Ordered Targets");
+
+    let num_points = [100_000, 1_000_000];
+    let num_targets = 10_000;
+
+    for &points in &num_points {
+        let random_points: Vec<Point<DIMENSIONS>> = (0..points)
+            .map(|_| Point::new_random())
+            .collect();
+
+        let vp_tree = vp_tree::VpTree::new_parallel(random_points, 16);
+
+        let mut targets: Vec<Point<DIMENSIONS>> = (0..num_targets)
+            .map(|_| Point::new_random())
+            .collect();
+        targets.sort_by_key(|target| hilbert_index(1024, target.cords[0] as u64, target.cords[1] as u64));
+
+        let query = vp_tree::Query::k_nearest_neighbors(10);
+
+        group.bench_function(format!("Independent K=10 nearest neighbors searches for {} Hilbert ordered targets in VpTree with {} points", num_targets, points),
+            |b| b.iter(|| {
+                let _k_nn = targets.iter().map(|target| vp_tree.query(black_box(target), &query)).collect::<Vec<_>>();
+            }),
+        );
+
+        group.bench_function(format!("Locality aware K=10 nearest neighbors searches for {} Hilbert ordered targets in VpTree with {} points", num_targets, points),
+            |b| b.iter(|| {
+                let _k_nn = vp_tree.query_batch_locality_aware(black_box(&targets), &query);
+            }),
+        );
+    }
+}
+
 fn nearest_neighbor_search_index(c: &mut Criterion) {
     let mut group = c.benchmark_group("VpTree Nearest Neighbor Search (Indirect access)");
 
@@ -121,7 +243,7 @@ fn nearest_neighbor_search_index(c: &mut Criterion) {
 
         group.bench_function(format!("Nearest neighbor search in VpTree with {} points", points),
             |b| b.iter_batched(
-                || Point::new_random(),
+                Point::new_random,
                 |target| {
                     let _nn = vp_tree.nearest_neighbor(black_box(&target));
                 },
@@ -147,9 +269,9 @@ fn k_nearest_neighbors_search(c: &mut Criterion) {
 
             group.bench_function(format!("K={} nearest neighbors search in VpTree with {} points", k, points),
                 |b| b.iter_batched(
-                    || Point::new_random(),
+                    Point::new_random,
                     |target| {
-                        let _k_nn = vp_tree.querry(black_box(&target), black_box(vp_tree::Querry::k_nearest_neighbors(*k)));
+                        let _k_nn = vp_tree.query(black_box(&target), black_box(vp_tree::Query::k_nearest_neighbors(*k)));
                     },
                     criterion::BatchSize::SmallInput,
                 ),
@@ -174,9 +296,9 @@ fn k_nearest_neighbors_search_index(c: &mut Criterion) {
 
             group.bench_function(format!("K={} nearest neighbors search in VpTree with {} points", k, points),
                 |b| b.iter_batched(
-                    || Point::new_random(),
+                    Point::new_random,
                     |target| {
-                        let _k_nn = vp_tree.querry(black_box(&target), black_box(vp_tree::Querry::k_nearest_neighbors(*k)));
+                        let _k_nn = vp_tree.query(black_box(&target), black_box(vp_tree::Query::k_nearest_neighbors(*k)));
                     },
                     criterion::BatchSize::SmallInput,
                 ),
@@ -185,6 +307,162 @@ fn k_nearest_neighbors_search_index(c: &mut Criterion) {
     }
 }
 
+fn vantage_selection_search(c: &mut Criterion) {
+    let mut group = c.benchmark_group("VpTree K Nearest Neighbors Search by Vantage Selection");
+
+    const SELECTION_DIMENSIONS: usize = 20;
+    let num_points = [100_000];
+    let selections = [vp_tree::VpSelection::Random, vp_tree::VpSelection::Corner];
+
+    for &points in &num_points {
+        let random_points: Vec<Point<SELECTION_DIMENSIONS>> = (0..points)
+            .map(|_| Point::new_random())
+            .collect();
+
+        for selection in selections {
+            let vp_tree = vp_tree::VpTree::new_with_selection(random_points.clone(), selection);
+
+            group.bench_function(format!("K=10 nearest neighbors search in VpTree with {} points in {} dimensions using {:?} selection", points, SELECTION_DIMENSIONS, selection),
+                |b| b.iter_batched(
+                    Point::new_random,
+                    |target| {
+                        let _k_nn = vp_tree.query(black_box(&target), black_box(vp_tree::Query::k_nearest_neighbors(10)));
+                    },
+                    criterion::BatchSize::SmallInput,
+                ),
+            );
+        }
+    }
+}
+
+const PAYLOAD_SIZE: usize = 256;
+
+#[derive(Clone)]
+struct PointWithPayload {
+    point: Point<DIMENSIONS>,
+    _payload: [u8; PAYLOAD_SIZE],
+}
+
+impl Distance<PointWithPayload> for PointWithPayload {
+    fn distance(&self, other: &PointWithPayload) -> f64 {
+        self.point.distance(&other.point)
+    }
+
+    fn distance_heuristic(&self, other: &PointWithPayload) -> f64 {
+        self.point.distance_heuristic(&other.point)
+    }
+}
+
+impl Distance<PointWithPayload> for Point<DIMENSIONS> {
+    fn distance(&self, other: &PointWithPayload) -> f64 {
+        self.distance(&other.point)
+    }
+}
+
+fn payload_layout_search(c: &mut Criterion) {
+    let mut group = c.benchmark_group("VpTree K Nearest Neighbors Search by Payload Layout");
+
+    let num_points = [1_000_000];
+
+    for &points in &num_points {
+        let random_points: Vec<Point<DIMENSIONS>> = (0..points)
+            .map(|_| Point::new_random())
+            .collect();
+        let payloads = vec![[0u8; PAYLOAD_SIZE]; points];
+
+        let combined = random_points.iter().cloned()
+            .zip(payloads.iter().cloned())
+            .map(|(point, payload)| PointWithPayload { point, _payload: payload })
+            .collect();
+        let combined_tree = vp_tree::VpTree::new_parallel(combined, 16);
+
+        group.bench_function(format!("K=10 nearest neighbors search in VpTree with {} points and {} byte payloads stored together", points, PAYLOAD_SIZE),
+            |b| b.iter_batched(
+                Point::new_random,
+                |target| {
+                    let _k_nn = combined_tree.query(black_box(&target), black_box(vp_tree::Query::k_nearest_neighbors(10)));
+                },
+                criterion::BatchSize::SmallInput,
+            ),
+        );
+
+        drop(combined_tree);
+        let separate_tree = vp_tree::VpTreeWithPayload::new_parallel(random_points, payloads, 16);
+
+        group.bench_function(format!("K=10 nearest neighbors search in VpTree with {} points and {} byte payloads stored separately", points, PAYLOAD_SIZE),
+            |b| b.iter_batched(
+                Point::new_random,
+                |target| {
+                    let _k_nn = separate_tree.query(black_box(&target), black_box(vp_tree::Query::k_nearest_neighbors(10)));
+                },
+                criterion::BatchSize::SmallInput,
+            ),
+        );
+    }
+}
+
+fn in_order_search(c: &mut Criterion) {
+    let mut group = c.benchmark_group("VpTree K Nearest Neighbors Search with Items in Input Order");
+
+    let num_points = [100_000, 1_000_000];
+
+    for &points in &num_points {
+        let random_points: Vec<Point<DIMENSIONS>> = (0..points)
+            .map(|_| Point::new_random())
+            .collect();
+
+        let vp_tree = vp_tree::VpTree::new_parallel(random_points.clone(), 16);
+
+        group.bench_function(format!("K=10 nearest neighbors search in VpTree with {} reordered points", points),
+            |b| b.iter_batched(
+                Point::new_random,
+                |target| {
+                    let _k_nn = vp_tree.query(black_box(&target), black_box(vp_tree::Query::k_nearest_neighbors(10)));
+                },
+                criterion::BatchSize::SmallInput,
+            ),
+        );
+
+        drop(vp_tree);
+        let in_order_tree = vp_tree::VpTreeInOrder::new_parallel(random_points, 16);
+
+        group.bench_function(format!("K=10 nearest neighbors search in VpTreeInOrder with {} points", points),
+            |b| b.iter_batched(
+                Point::new_random,
+                |target| {
+                    let _k_nn = in_order_tree.query(black_box(&target), black_box(vp_tree::Query::k_nearest_neighbors(10)));
+                },
+                criterion::BatchSize::SmallInput,
+            ),
+        );
+    }
+}
+
+/// Run with and without `--features inline-knn` to compare the inline buffer against the [`std::collections::BinaryHeap`] path.
+fn small_k_search(c: &mut Criterion) {
+    let mut group = c.benchmark_group("VpTree Small K Nearest Neighbors Search");
+
+    let num_points = [10_000, 1_000_000];
+
+    for &points in &num_points {
+        let random_points: Vec<Point<DIMENSIONS>> = (0..points)
+            .map(|_| Point::new_random())
+            .collect();
+
+        let vp_tree = vp_tree::VpTree::new_parallel(random_points, 16);
+
+        group.bench_function(format!("K=8 nearest neighbors search in VpTree with {} points (inline-knn: {})", points, cfg!(feature = "inline-knn")),
+            |b| b.iter_batched(
+                Point::new_random,
+                |target| {
+                    let _k_nn = vp_tree.query(black_box(&target), black_box(vp_tree::Query::k_nearest_neighbors(8)));
+                },
+                criterion::BatchSize::SmallInput,
+            ),
+        );
+    }
+}
+
 fn radius_search(c: &mut Criterion) {
     let mut group = c.benchmark_group("VpTree Radius Search");
 
@@ -201,9 +479,9 @@ fn radius_search(c: &mut Criterion) {
 
             group.bench_function(format!("Radius={} search in VpTree with {} points", radius, points),
                 |b| b.iter_batched(
-                    || Point::new_random(),
+                    Point::new_random,
                     |target| {
-                        let _in_radius = vp_tree.querry(black_box(&target), black_box(vp_tree::Querry::neighbors_within_radius(radius)));
+                        let _in_radius = vp_tree.query(black_box(&target), black_box(vp_tree::Query::neighbors_within_radius(radius)));
                     },
                     criterion::BatchSize::SmallInput,
                 ),
@@ -228,9 +506,9 @@ fn radius_search_index(c: &mut Criterion) {
 
             group.bench_function(format!("Radius={} search in VpTree with {} points", radius, points),
                 |b| b.iter_batched(
-                    || Point::new_random(),
+                    Point::new_random,
                     |target| {
-                        let _in_radius = vp_tree.querry(black_box(&target), black_box(vp_tree::Querry::neighbors_within_radius(radius)));
+                        let _in_radius = vp_tree.query(black_box(&target), black_box(vp_tree::Query::neighbors_within_radius(radius)));
                     },
                     criterion::BatchSize::SmallInput,
                 ),
@@ -239,9 +517,9 @@ fn radius_search_index(c: &mut Criterion) {
     }
 }
 
-criterion_group!(benches1, construction, construction_index);
-criterion_group!(benches2, nearest_neighbor_search, nearest_neighbor_search_index);
-criterion_group!(benches3, k_nearest_neighbors_search, k_nearest_neighbors_search_index);
+criterion_group!(benches1, construction, construction_limited, construction_index);
+criterion_group!(benches2, nearest_neighbor_search, nearest_neighbor_search_index, nearest_neighbor_along_path, k_nearest_neighbors_batch_locality_aware);
+criterion_group!(benches3, k_nearest_neighbors_search, k_nearest_neighbors_search_index, vantage_selection_search, payload_layout_search, in_order_search, small_k_search);
 criterion_group!(benches4, radius_search, radius_search_index);
 
 criterion_main!(benches1, benches2, benches3, benches4);
\ No newline at end of file
@@ -69,17 +69,17 @@ fn main() {
     println!("Time taken to search nearest neighbor: {:?}, {:.2?} times faster than linear search. Result: {:?}", duration, baseline_duration.as_secs_f64() / duration.as_secs_f64(), nearest_neighbor);
 
     let start = std::time::Instant::now();
-    let k_closest_neighbors = vp_tree.querry(&target_point, Querry::k_nearest_neighbors(5));
+    let k_closest_neighbors = vp_tree.query(&target_point, Query::k_nearest_neighbors(5));
     let duration = start.elapsed();
     println!("Time taken to search 5 closest neighbors: {:?}, {:.2?} times faster than linear search. Result count: {}", duration, k_baseline_duration.as_secs_f64() / duration.as_secs_f64(), k_closest_neighbors.len());
 
     let start = std::time::Instant::now();
-    let in_radius = vp_tree.querry(&target_point, Querry::neighbors_within_radius(2.0));
+    let in_radius = vp_tree.query(&target_point, Query::neighbors_within_radius(2.0));
     let duration = start.elapsed();
     println!("Time taken to search points within radius 2.0: {:?}, {:.2?} times faster than linear search. Result count: {}", duration, radius_baseline_duration.as_secs_f64() / duration.as_secs_f64(), in_radius.len());
 }
 
-fn find_nearest_neighbor_linear<'a>(points: &'a Vec<Point>, target: &Point) -> Option<&'a Point> {
+fn find_nearest_neighbor_linear<'a>(points: &'a [Point], target: &Point) -> Option<&'a Point> {
     points.iter().min_by(|a, b| {
         let dist_a = a.distance_heuristic(&target);
         let dist_b = b.distance_heuristic(&target);
@@ -87,7 +87,7 @@ fn find_nearest_neighbor_linear<'a>(points: &'a Vec<Point>, target: &Point) -> O
     })
 }
 
-fn find_k_closest_linear<'a>(points: &'a Vec<Point>, target: &Point, k: usize) -> Vec<&'a Point> {
+fn find_k_closest_linear<'a>(points: &'a [Point], target: &Point, k: usize) -> Vec<&'a Point> {
     let mut binary_heap = BinaryHeap::new();
 
     for point in points.iter() {
@@ -121,8 +121,7 @@ impl<'a> Eq for HeapItemHelper<'a> {}
 
 impl<'a> PartialOrd for HeapItemHelper<'a> {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        // Reverse order for min-heap behavior
-        other.distance.partial_cmp(&self.distance)
+        Some(self.cmp(other))
     }
 }
 
@@ -133,7 +132,7 @@ impl<'a> Ord for HeapItemHelper<'a> {
     }
 }
 
-fn find_in_radius_linear<'a>(points: &'a Vec<Point>, target: &Point, radius: f64) -> Vec<&'a Point> {
+fn find_in_radius_linear<'a>(points: &'a [Point], target: &Point, radius: f64) -> Vec<&'a Point> {
     points
         .iter()
         .filter(|p| p.distance_heuristic(&target) <= radius * radius)
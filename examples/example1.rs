@@ -34,6 +34,6 @@ fn main() {
     let vp_tree = VpTree::new_parallel(random_points, 4);
     
     let _nearest_neighbor = vp_tree.nearest_neighbor(&target_point);
-    let _k_closest_neighbors = vp_tree.querry(&target_point, Querry::k_nearest_neighbors(5));
-    let _in_radius = vp_tree.querry(&target_point, Querry::neighbors_within_radius(100.0));
+    let _k_closest_neighbors = vp_tree.query(&target_point, Query::k_nearest_neighbors(5));
+    let _in_radius = vp_tree.query(&target_point, Query::neighbors_within_radius(100.0));
 }
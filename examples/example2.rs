@@ -43,12 +43,12 @@ fn main() {
     let target_point = Point { x: 500.0, y: 500.0 };
 
     let _nearest_neighbor = vp_tree.nearest_neighbor(&target_point);
-    let _k_closest_neighbors = vp_tree.querry(&target_point, Querry::k_nearest_neighbors(5));
-    let _in_radius = vp_tree.querry(&target_point, Querry::neighbors_within_radius(100.0));
+    let _k_closest_neighbors = vp_tree.query(&target_point, Query::k_nearest_neighbors(5));
+    let _in_radius = vp_tree.query(&target_point, Query::neighbors_within_radius(100.0));
 
-    let full_querry = Querry::k_nearest_neighbors(5)
+    let full_query = Query::k_nearest_neighbors(5)
         .within_radius(100.0)
         .sorted()
         .exclusive();
-    let _custom_querry = vp_tree.querry(&target_point, full_querry);
+    let _custom_query = vp_tree.query(&target_point, full_query);
 }
\ No newline at end of file
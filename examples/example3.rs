@@ -32,6 +32,6 @@ fn main() {
     let vp_tree = vp_tree::VpTree::new_index_parallel(&random_points, 4);
     
     let _nearest_neighbor = vp_tree.nearest_neighbor(&target_point);
-    let _k_closest_neighbors = vp_tree.querry(&target_point, vp_tree::Querry::k_nearest_neighbors(5));
-    let _in_radius = vp_tree.querry(&target_point, vp_tree::Querry::neighbors_within_radius(100.0));
+    let _k_closest_neighbors = vp_tree.query(&target_point, vp_tree::Query::k_nearest_neighbors(5));
+    let _in_radius = vp_tree.query(&target_point, vp_tree::Query::neighbors_within_radius(100.0));
 }
\ No newline at end of file
@@ -0,0 +1,52 @@
+use std::sync::{Arc, RwLock};
+
+use vp_tree::*;
+
+struct Point {
+    x: f64,
+    y: f64,
+}
+
+impl Distance<Point> for Point {
+    fn distance(&self, other: &Point) -> f64 {
+        self.distance_heuristic(other).sqrt()
+    }
+
+    fn distance_heuristic(&self, other: &Point) -> f64 {
+        let dx = self.x - other.x;
+        let dy = self.y - other.y;
+        dx * dx + dy * dy
+    }
+}
+
+fn random_points(count: usize) -> Vec<Point> {
+    (0..count)
+        .map(|_| Point {
+            x: fastrand::f64() * 1000.0,
+            y: fastrand::f64() * 1000.0,
+        })
+        .collect()
+}
+
+fn main() {
+    // The current snapshot. Readers only hold the lock long enough to clone the Arc.
+    let snapshot = Arc::new(RwLock::new(Arc::new(VpTree::new(random_points(10_000)))));
+
+    std::thread::scope(|s| {
+        for _ in 0..4 {
+            let snapshot = Arc::clone(&snapshot);
+            s.spawn(move || {
+                for _ in 0..1_000 {
+                    // Keep using this snapshot until the query is done, even if it is replaced in the meantime
+                    let vp_tree = Arc::clone(&snapshot.read().unwrap());
+                    let target = Point { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 };
+                    let _k_closest_neighbors = vp_tree.query(&target, Query::k_nearest_neighbors(5));
+                }
+            });
+        }
+
+        // Build the replacement without blocking the readers, then swap it in
+        let replacement = Arc::new(VpTree::new_parallel(random_points(10_000), 4));
+        *snapshot.write().unwrap() = replacement;
+    });
+}
@@ -0,0 +1,23 @@
+use vp_tree::*;
+use vp_tree::metrics::GeoPoint;
+
+fn main() {
+    // Random locations in and around Berlin
+    let locations = (0..100_000)
+        .map(|_| GeoPoint {
+            lat: 52.3 + fastrand::f64() * 0.4,
+            lon: 13.0 + fastrand::f64() * 0.8,
+        })
+        .collect::<Vec<_>>();
+
+    let vp_tree = VpTree::new(locations);
+
+    let brandenburg_gate = GeoPoint { lat: 52.5163, lon: 13.3777 };
+
+    // All locations within 5km of the Brandenburg Gate
+    let within_5km = vp_tree.query(&brandenburg_gate, Query::neighbors_within_radius(5_000.0));
+    println!("{} locations within 5km", within_5km.len());
+
+    let nearest = vp_tree.nearest_neighbor(&brandenburg_gate).unwrap();
+    println!("Nearest location: {:?}, {:.1}m away", nearest, nearest.distance(&brandenburg_gate));
+}
@@ -0,0 +1,478 @@
+use std::{collections::BinaryHeap, ops::ControlFlow};
+
+use crate::{OrdKey, Query, SearchStats, counter::DistanceCounter};
+
+/// Receives the items visited during a search of the [`crate::VpTree`] and decides how far the search extends.
+pub(crate) trait Collector {
+    /// Current search radius. Subtrees that can not contain items within this distance of the target are pruned.
+    /// Collectors of a [`Query`] widen the radius by its boundary epsilon, see [`Query::boundary_epsilon`].
+    fn tau(&self) -> f64;
+
+    /// Called for every visited item with its distance to the target. Returning [`ControlFlow::Break`] stops the search.
+    fn visit(&mut self, index: usize, distance: f64) -> ControlFlow<()>;
+}
+
+/// Collects the nearest items matching a [`Query`] in a max-heap.
+pub(crate) struct KnnCollector<'a> {
+    pub(crate) heap: BinaryHeap<HeapItem>,
+    tau: f64,
+    query: &'a Query,
+    excluded_index: Option<usize>,
+}
+
+impl<'a> KnnCollector<'a> {
+    pub(crate) fn new(query: &'a Query) -> Self {
+        KnnCollector {
+            heap: BinaryHeap::new(),
+            tau: query.max_distance,
+            query,
+            excluded_index: None,
+        }
+    }
+
+    /// Skips the item stored at `index` regardless of its distance.
+    pub(crate) fn excluding(mut self, index: usize) -> Self {
+        self.excluded_index = Some(index);
+        self
+    }
+}
+
+impl Collector for KnnCollector<'_> {
+    #[inline(always)]
+    fn tau(&self) -> f64 {
+        self.tau + self.query.boundary_epsilon
+    }
+
+    #[inline(always)]
+    fn visit(&mut self, index: usize, distance: f64) -> ControlFlow<()> {
+        if distance <= self.tau && self.query.accepts(distance) && self.excluded_index != Some(index) {
+            if self.heap.len() == self.query.max_items {
+                self.heap.pop();
+            }
+            self.heap.push(HeapItem { index, distance });
+            if self.heap.len() == self.query.max_items && let Some(peek) = self.heap.peek() {
+                self.tau = peek.distance;
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Collects up to `K` nearest items matching a [`Query`] in an inline array sorted by distance, avoiding heap allocations during the search.
+#[cfg(feature = "inline-knn")]
+pub(crate) struct InlineKnnCollector<'a, const K: usize> {
+    items: [HeapItem; K],
+    len: usize,
+    tau: f64,
+    query: &'a Query,
+}
+
+#[cfg(feature = "inline-knn")]
+impl<'a, const K: usize> InlineKnnCollector<'a, K> {
+    /// Panics if the query requests more than `K` items.
+    pub(crate) fn new(query: &'a Query) -> Self {
+        assert!(query.max_items <= K, "query requests more items than the inline capacity");
+        InlineKnnCollector {
+            items: [HeapItem { index: 0, distance: f64::INFINITY }; K],
+            len: 0,
+            tau: query.max_distance,
+            query,
+        }
+    }
+
+    /// Returns the collected items sorted by distance.
+    pub(crate) fn into_vec(self) -> Vec<HeapItem> {
+        self.items[..self.len].to_vec()
+    }
+}
+
+#[cfg(feature = "inline-knn")]
+impl<const K: usize> Collector for InlineKnnCollector<'_, K> {
+    #[inline(always)]
+    fn tau(&self) -> f64 {
+        self.tau + self.query.boundary_epsilon
+    }
+
+    #[inline(always)]
+    fn visit(&mut self, index: usize, distance: f64) -> ControlFlow<()> {
+        if distance <= self.tau && self.query.accepts(distance) {
+            // A full buffer drops its farthest item to make room.
+            let mut position = self.len.min(self.query.max_items - 1);
+            self.len = (self.len + 1).min(self.query.max_items);
+            while position > 0 && self.items[position - 1].distance > distance {
+                self.items[position] = self.items[position - 1];
+                position -= 1;
+            }
+            self.items[position] = HeapItem { index, distance };
+            if self.len == self.query.max_items {
+                self.tau = self.items[self.len - 1].distance;
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Collects the nearest items matching a [`Query`] in a max-heap, breaking distance ties by the [`OrdKey`] of the items.
+pub(crate) struct OrderedKnnCollector<'a, T: OrdKey> {
+    pub(crate) heap: BinaryHeap<OrderedHeapItem<T::Key>>,
+    tau: f64,
+    query: &'a Query,
+    items: &'a [T],
+}
+
+impl<'a, T: OrdKey> OrderedKnnCollector<'a, T> {
+    pub(crate) fn new(query: &'a Query, items: &'a [T]) -> Self {
+        OrderedKnnCollector {
+            heap: BinaryHeap::new(),
+            tau: query.max_distance,
+            query,
+            items,
+        }
+    }
+}
+
+impl<T: OrdKey> Collector for OrderedKnnCollector<'_, T> {
+    #[inline(always)]
+    fn tau(&self) -> f64 {
+        self.tau + self.query.boundary_epsilon
+    }
+
+    #[inline(always)]
+    fn visit(&mut self, index: usize, distance: f64) -> ControlFlow<()> {
+        if distance <= self.tau && self.query.accepts(distance) {
+            let item = OrderedHeapItem { index, distance, key: self.items[index].ord_key() };
+            if self.heap.len() == self.query.max_items {
+                if self.heap.peek().is_some_and(|peek| item >= *peek) {
+                    return ControlFlow::Continue(());
+                }
+                self.heap.pop();
+            }
+            self.heap.push(item);
+            if self.heap.len() == self.query.max_items && let Some(peek) = self.heap.peek() {
+                self.tau = peek.distance;
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Tracks the single nearest item.
+pub(crate) struct NearestCollector {
+    pub(crate) best_index: Option<usize>,
+    pub(crate) best_distance: f64,
+    exclusive: bool,
+    excluded_index: Option<usize>,
+}
+
+impl NearestCollector {
+    pub(crate) fn new(exclusive: bool) -> Self {
+        NearestCollector {
+            best_index: None,
+            best_distance: f64::INFINITY,
+            exclusive,
+            excluded_index: None,
+        }
+    }
+
+    /// Skips the item stored at `index` regardless of its distance.
+    pub(crate) fn excluding(mut self, index: usize) -> Self {
+        self.excluded_index = Some(index);
+        self
+    }
+
+    /// Starts the search with a known candidate, pruning everything farther away from the start.
+    pub(crate) fn seeded(mut self, index: usize, distance: f64) -> Self {
+        self.best_index = Some(index);
+        self.best_distance = distance;
+        self
+    }
+}
+
+impl Collector for NearestCollector {
+    #[inline(always)]
+    fn tau(&self) -> f64 {
+        self.best_distance
+    }
+
+    #[inline(always)]
+    fn visit(&mut self, index: usize, distance: f64) -> ControlFlow<()> {
+        if distance < self.best_distance && (!self.exclusive || distance > 0.0) && self.excluded_index != Some(index) {
+            self.best_distance = distance;
+            self.best_index = Some(index);
+            if distance == 0.0 {
+                // Distances are non-negative, no closer item exists.
+                return ControlFlow::Break(());
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Tracks the nearest item at or beyond a minimum distance.
+pub(crate) struct NearestBeyondCollector {
+    pub(crate) best_index: Option<usize>,
+    pub(crate) best_distance: f64,
+    min_distance: f64,
+}
+
+impl NearestBeyondCollector {
+    pub(crate) fn new(min_distance: f64) -> Self {
+        NearestBeyondCollector {
+            best_index: None,
+            best_distance: f64::INFINITY,
+            min_distance,
+        }
+    }
+}
+
+impl Collector for NearestBeyondCollector {
+    #[inline(always)]
+    fn tau(&self) -> f64 {
+        self.best_distance
+    }
+
+    #[inline(always)]
+    fn visit(&mut self, index: usize, distance: f64) -> ControlFlow<()> {
+        if distance < self.best_distance && distance >= self.min_distance {
+            self.best_distance = distance;
+            self.best_index = Some(index);
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Tracks all items tied for the smallest distance.
+pub(crate) struct AllNearestCollector {
+    pub(crate) indices: Vec<usize>,
+    best_distance: f64,
+}
+
+impl AllNearestCollector {
+    pub(crate) fn new() -> Self {
+        AllNearestCollector {
+            indices: Vec::new(),
+            best_distance: f64::INFINITY,
+        }
+    }
+}
+
+impl Collector for AllNearestCollector {
+    #[inline(always)]
+    fn tau(&self) -> f64 {
+        self.best_distance
+    }
+
+    #[inline(always)]
+    fn visit(&mut self, index: usize, distance: f64) -> ControlFlow<()> {
+        if distance < self.best_distance {
+            self.best_distance = distance;
+            self.indices.clear();
+            self.indices.push(index);
+        } else if distance == self.best_distance {
+            self.indices.push(index);
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Stops at the first item within a fixed radius.
+pub(crate) struct ExistsCollector {
+    radius: f64,
+    pub(crate) found: bool,
+}
+
+impl ExistsCollector {
+    pub(crate) fn new(radius: f64) -> Self {
+        ExistsCollector { radius, found: false }
+    }
+}
+
+impl Collector for ExistsCollector {
+    #[inline(always)]
+    fn tau(&self) -> f64 {
+        self.radius
+    }
+
+    #[inline(always)]
+    fn visit(&mut self, _index: usize, distance: f64) -> ControlFlow<()> {
+        if distance <= self.radius {
+            self.found = true;
+            return ControlFlow::Break(());
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Passes every item within a fixed radius to a callback as soon as it is found.
+pub(crate) struct ScanCollector<'a, F> {
+    query: &'a Query,
+    f: F,
+}
+
+impl<'a, F> ScanCollector<'a, F>
+where
+    F: FnMut(usize, f64) -> ControlFlow<()>,
+{
+    pub(crate) fn new(query: &'a Query, f: F) -> Self {
+        ScanCollector { query, f }
+    }
+}
+
+impl<F> Collector for ScanCollector<'_, F>
+where
+    F: FnMut(usize, f64) -> ControlFlow<()>,
+{
+    #[inline(always)]
+    fn tau(&self) -> f64 {
+        self.query.max_distance + self.query.boundary_epsilon
+    }
+
+    #[inline(always)]
+    fn visit(&mut self, index: usize, distance: f64) -> ControlFlow<()> {
+        if distance <= self.query.max_distance && self.query.accepts(distance) {
+            return (self.f)(index, distance);
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Limits the number of nodes another collector may visit.
+pub(crate) struct BudgetCollector<C> {
+    pub(crate) inner: C,
+    remaining: usize,
+    pub(crate) exhausted: bool,
+}
+
+impl<C: Collector> BudgetCollector<C> {
+    pub(crate) fn new(inner: C, node_budget: usize) -> Self {
+        BudgetCollector {
+            inner,
+            remaining: node_budget,
+            exhausted: false,
+        }
+    }
+}
+
+impl<C: Collector> Collector for BudgetCollector<C> {
+    #[inline(always)]
+    fn tau(&self) -> f64 {
+        self.inner.tau()
+    }
+
+    #[inline(always)]
+    fn visit(&mut self, index: usize, distance: f64) -> ControlFlow<()> {
+        if self.remaining == 0 {
+            self.exhausted = true;
+            return ControlFlow::Break(());
+        }
+        self.remaining -= 1;
+        self.inner.visit(index, distance)
+    }
+}
+
+/// Records [`SearchStats`] while forwarding every visit to the inner collector.
+pub(crate) struct StatsCollector<C> {
+    pub(crate) inner: C,
+    pub(crate) stats: SearchStats,
+}
+
+impl<C: Collector> StatsCollector<C> {
+    pub(crate) fn new(inner: C) -> Self {
+        StatsCollector {
+            inner,
+            stats: SearchStats::default(),
+        }
+    }
+}
+
+impl<C: Collector> Collector for StatsCollector<C> {
+    #[inline(always)]
+    fn tau(&self) -> f64 {
+        self.inner.tau()
+    }
+
+    #[inline(always)]
+    fn visit(&mut self, index: usize, distance: f64) -> ControlFlow<()> {
+        self.stats.nodes_visited += 1;
+        self.inner.visit(index, distance)
+    }
+}
+
+/// Forwards the visits of a search over a tree of item offsets to a collector of the items, counting each visit as a distance evaluation.
+pub(crate) struct OffsetCollector<'a, C> {
+    inner: &'a mut C,
+    offsets: &'a [usize],
+    start: usize,
+    distance_calls: &'a DistanceCounter,
+}
+
+impl<'a, C: Collector> OffsetCollector<'a, C> {
+    pub(crate) fn new(inner: &'a mut C, offsets: &'a [usize], start: usize, distance_calls: &'a DistanceCounter) -> Self {
+        OffsetCollector { inner, offsets, start, distance_calls }
+    }
+}
+
+impl<C: Collector> Collector for OffsetCollector<'_, C> {
+    #[inline(always)]
+    fn tau(&self) -> f64 {
+        self.inner.tau()
+    }
+
+    #[inline(always)]
+    fn visit(&mut self, index: usize, distance: f64) -> ControlFlow<()> {
+        self.distance_calls.increment();
+        self.inner.visit(self.start + self.offsets[index], distance)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct HeapItem {
+    pub(crate) index: usize,
+    pub(crate) distance: f64,
+}
+
+impl PartialEq for HeapItem {
+    fn eq(&self, other: &Self) -> bool {
+        self.distance == other.distance
+    }
+}
+impl Eq for HeapItem {}
+
+impl PartialOrd for HeapItem {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HeapItem {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.distance.partial_cmp(&other.distance).unwrap_or(std::cmp::Ordering::Less)
+    }
+}
+
+/// [`HeapItem`] that orders equal distances by key, a higher key is considered closer.
+pub(crate) struct OrderedHeapItem<K> {
+    pub(crate) index: usize,
+    pub(crate) distance: f64,
+    pub(crate) key: K,
+}
+
+impl<K: Ord> PartialEq for OrderedHeapItem<K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+impl<K: Ord> Eq for OrderedHeapItem<K> {}
+
+impl<K: Ord> PartialOrd for OrderedHeapItem<K> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord> Ord for OrderedHeapItem<K> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.distance.partial_cmp(&other.distance).unwrap_or(std::cmp::Ordering::Less)
+            .then_with(|| other.key.cmp(&self.key))
+    }
+}
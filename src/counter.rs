@@ -0,0 +1,40 @@
+/// Counts distance evaluations during searches. Without the `instrument` feature this is a zero-sized no-op.
+#[derive(Debug, Default)]
+pub(crate) struct DistanceCounter {
+    #[cfg(feature = "instrument")]
+    count: std::sync::atomic::AtomicU64,
+}
+
+impl DistanceCounter {
+    #[inline(always)]
+    pub(crate) fn increment(&self) {
+        #[cfg(feature = "instrument")]
+        self.count.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    #[cfg(feature = "instrument")]
+    pub(crate) fn get(&self) -> u64 {
+        self.count.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    #[cfg(feature = "instrument")]
+    pub(crate) fn reset(&self) {
+        self.count.store(0, std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+impl Clone for DistanceCounter {
+    fn clone(&self) -> Self {
+        DistanceCounter {
+            #[cfg(feature = "instrument")]
+            count: std::sync::atomic::AtomicU64::new(self.get()),
+        }
+    }
+}
+
+/// Counters are not part of the tree contents and do not affect equality.
+impl PartialEq for DistanceCounter {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
@@ -1,8 +1,11 @@
+use std::sync::Arc;
+
 
 /// [`Distance`] trait to be implemented by types stored in the [`crate::VpTree`].
 /// 
 /// 
 /// The [`Distance::distance`] method should return a non-negative [f64] representing the distance between self and other.
+/// Negative distances break the pruning of searches, debug builds panic when one is encountered.
 /// In case there is an efficient way to compute the squared distance, the [`Distance::distance_heuristic`] method can be overridden 
 /// to accelerate the building of the [`crate::VpTree`].
 /// Elements in the tree have to implement [`Distance`] to themselves. Additionally, search targets can implement [`Distance`] to the stored type.
@@ -62,9 +65,21 @@ pub trait Distance<T> {
 
     /// Use this method to provide a more efficient squared distance calculation if possible to prevent unnecessary square root calculations during build of the [`crate::VpTree`].
     /// By default, this method calls [`Distance::distance`]. 
+    /// 
+    /// The heuristic is only used to order items during construction, it has to be monotonic in relation to [`Distance::distance`].
+    /// Thresholds and search always use [`Distance::distance`], since pruning relies on the triangle inequality which squared distances do not satisfy.
     fn distance_heuristic(&self, other: &T) -> f64 {
         self.distance(other)
     }
+
+    /// Cheap lower bound on [`Distance::distance`], used to skip expensive distance calculations during searches.
+    /// By default, this method returns `0.0`, which never skips a calculation.
+    /// 
+    /// Items whose lower bound exceeds the current search radius are pruned without calling [`Distance::distance`].
+    /// Returning a value larger than the true distance causes searches to miss items.
+    fn cheap_lower_bound(&self, _other: &T) -> f64 {
+        0.0
+    }
 }
 
 impl<'a, T: Distance<T>> Distance<&'a T> for &'a T {
@@ -74,6 +89,9 @@ impl<'a, T: Distance<T>> Distance<&'a T> for &'a T {
     fn distance_heuristic(&self, other: &&'a T) -> f64 {
         (*self).distance_heuristic(*other)
     }
+    fn cheap_lower_bound(&self, other: &&'a T) -> f64 {
+        (*self).cheap_lower_bound(*other)
+    }
 }
 
 impl<T: Distance<T>> Distance<T> for &T {
@@ -83,6 +101,9 @@ impl<T: Distance<T>> Distance<T> for &T {
     fn distance_heuristic(&self, other: &T) -> f64 {
         (*self).distance_heuristic(other)
     }
+    fn cheap_lower_bound(&self, other: &T) -> f64 {
+        (*self).cheap_lower_bound(other)
+    }
 }
 
 impl<T: Distance<T>> Distance<&T> for T {
@@ -92,4 +113,79 @@ impl<T: Distance<T>> Distance<&T> for T {
     fn distance_heuristic(&self, other: &&T) -> f64 {
         self.distance_heuristic(*other)
     }
-}
\ No newline at end of file
+    fn cheap_lower_bound(&self, other: &&T) -> f64 {
+        self.cheap_lower_bound(*other)
+    }
+}
+
+impl<T: Distance<T>> Distance<Arc<T>> for Arc<T> {
+    fn distance(&self, other: &Arc<T>) -> f64 {
+        (**self).distance(&**other)
+    }
+    fn distance_heuristic(&self, other: &Arc<T>) -> f64 {
+        (**self).distance_heuristic(&**other)
+    }
+    fn cheap_lower_bound(&self, other: &Arc<T>) -> f64 {
+        (**self).cheap_lower_bound(&**other)
+    }
+}
+
+impl<T: Distance<T>> Distance<T> for Arc<T> {
+    fn distance(&self, other: &T) -> f64 {
+        (**self).distance(other)
+    }
+    fn distance_heuristic(&self, other: &T) -> f64 {
+        (**self).distance_heuristic(other)
+    }
+    fn cheap_lower_bound(&self, other: &T) -> f64 {
+        (**self).cheap_lower_bound(other)
+    }
+}
+
+impl<T: Distance<T>> Distance<Arc<T>> for T {
+    fn distance(&self, other: &Arc<T>) -> f64 {
+        self.distance(&**other)
+    }
+    fn distance_heuristic(&self, other: &Arc<T>) -> f64 {
+        self.distance_heuristic(&**other)
+    }
+    fn cheap_lower_bound(&self, other: &Arc<T>) -> f64 {
+        self.cheap_lower_bound(&**other)
+    }
+}
+
+impl<T: Distance<T>> Distance<Box<T>> for Box<T> {
+    fn distance(&self, other: &Box<T>) -> f64 {
+        (**self).distance(&**other)
+    }
+    fn distance_heuristic(&self, other: &Box<T>) -> f64 {
+        (**self).distance_heuristic(&**other)
+    }
+    fn cheap_lower_bound(&self, other: &Box<T>) -> f64 {
+        (**self).cheap_lower_bound(&**other)
+    }
+}
+
+impl<T: Distance<T>> Distance<T> for Box<T> {
+    fn distance(&self, other: &T) -> f64 {
+        (**self).distance(other)
+    }
+    fn distance_heuristic(&self, other: &T) -> f64 {
+        (**self).distance_heuristic(other)
+    }
+    fn cheap_lower_bound(&self, other: &T) -> f64 {
+        (**self).cheap_lower_bound(other)
+    }
+}
+
+impl<T: Distance<T>> Distance<Box<T>> for T {
+    fn distance(&self, other: &Box<T>) -> f64 {
+        self.distance(&**other)
+    }
+    fn distance_heuristic(&self, other: &Box<T>) -> f64 {
+        self.distance_heuristic(&**other)
+    }
+    fn cheap_lower_bound(&self, other: &Box<T>) -> f64 {
+        self.cheap_lower_bound(&**other)
+    }
+}
@@ -0,0 +1,112 @@
+use std::borrow::Borrow;
+
+use crate::{Distance, Query, VpTree, payload_tree::Indexed};
+
+/// A [`VpTree`] that keeps its items in input order.
+/// 
+/// Instead of reordering the items during construction, the tree reorders a permutation of item indices and searches index through it.
+/// This keeps [`Self::items`] aligned with data stored in parallel arrays, at the cost of one extra indirection per visited node.
+/// 
+/// ## Example
+/// ```rust
+/// use vp_tree::{Distance, Query, VpTreeInOrder};
+/// 
+/// #[derive(Debug, PartialEq)]
+/// struct Point {
+///     x: f64,
+///     y: f64,
+/// }
+/// 
+/// impl Distance<Point> for Point {
+///     fn distance(&self, other: &Point) -> f64 {
+///         ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+///     }
+/// }
+/// 
+/// let points = vec![Point { x: 2.0, y: 2.0 }, Point { x: 0.0, y: 0.0 }, Point { x: 1.0, y: 1.0 }];
+/// let labels = ["C", "A", "B"];
+/// 
+/// let vp_tree = VpTreeInOrder::new(points);
+/// assert_eq!(vp_tree.items()[0], Point { x: 2.0, y: 2.0 });
+/// 
+/// let index = vp_tree.nearest_neighbor_index(&Point { x: 0.9, y: 1.2 }).unwrap();
+/// assert_eq!(labels[index], "B");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct VpTreeInOrder<T> {
+    items: Vec<T>,
+    tree: VpTree<usize>,
+}
+
+impl<T: Distance<T>> VpTreeInOrder<T> {
+    /// Constructs a new [`VpTreeInOrder`] from a [`Vec`] of items without reordering them. See [`VpTree::new`].
+    pub fn new(items: Vec<T>) -> Self {
+        let tree = Self::build(VpTree::new(Self::indexed(&items)));
+        VpTreeInOrder { items, tree }
+    }
+
+    /// Constructs a new [`VpTreeInOrder`] using multiple threads. See [`VpTree::new_parallel`].
+    pub fn new_parallel(items: Vec<T>, threads: usize) -> Self
+    where
+        T: Sync,
+    {
+        let tree = Self::build(VpTree::new_parallel(Self::indexed(&items), threads));
+        VpTreeInOrder { items, tree }
+    }
+
+    /// Performs a query on the tree using the specified target and query parameters. See [`VpTree::query`].
+    pub fn query<U, Q>(&self, target: &U, query: Q) -> Vec<&T>
+    where
+        U: Distance<T>,
+        Q: Borrow<Query>,
+    {
+        self.query_indices(target, query)
+            .into_iter()
+            .map(|index| &self.items[index])
+            .collect()
+    }
+
+    /// Performs a query on the tree and returns the input indices of the matching items. See [`VpTree::query`].
+    pub fn query_indices<U, Q>(&self, target: &U, query: Q) -> Vec<usize>
+    where
+        U: Distance<T>,
+        Q: Borrow<Query>,
+    {
+        self.tree.search_items_by(target, query.borrow(), &|index| &self.items[*index])
+            .into_iter()
+            .map(|item| self.tree.items()[item.index])
+            .collect()
+    }
+
+    /// Searches for the single nearest neighbor to the target. See [`VpTree::nearest_neighbor`].
+    pub fn nearest_neighbor<U: Distance<T>>(&self, target: &U) -> Option<&T> {
+        self.nearest_neighbor_index(target).map(|index| &self.items[index])
+    }
+
+    /// Searches for the input index of the nearest neighbor to the target.
+    pub fn nearest_neighbor_index<U: Distance<T>>(&self, target: &U) -> Option<usize> {
+        self.query_indices(target, Query::k_nearest_neighbors(1)).pop()
+    }
+
+    /// Returns the items in the order they were passed to the constructor.
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+
+    /// Consumes the tree and returns the items in the order they were passed to the constructor.
+    pub fn into_items(self) -> Vec<T> {
+        self.items
+    }
+
+    fn indexed(items: &[T]) -> Vec<Indexed<&T>> {
+        items.iter()
+            .enumerate()
+            .map(|(index, item)| Indexed { item, index })
+            .collect()
+    }
+
+    /// Replaces every item of the built tree by its input index, the tree then searches the items through these indices.
+    fn build(tree: VpTree<Indexed<&T>>) -> VpTree<usize> {
+        tree.map_items(|indexed| indexed.index)
+    }
+}
@@ -58,10 +58,10 @@
 //! let nearest_neighbor = vp_tree.nearest_neighbor(&target);
 //! assert_eq!(nearest_neighbor.unwrap(), &Point { x: 2.0, y: 2.0 });
 //! 
-//! let k_nearest = vp_tree.querry(&target, Querry::k_nearest_neighbors(2).sorted());
+//! let k_nearest = vp_tree.query(&target, Query::k_nearest_neighbors(2).sorted());
 //! assert_eq!(k_nearest, vec![&Point { x: 2.0, y: 2.0 }, &Point { x: 3.0, y: 3.0 }]);
 //! 
-//! let radius_neighbors = vp_tree.querry(&target, Querry::neighbors_within_radius(1.0).sorted());
+//! let radius_neighbors = vp_tree.query(&target, Query::neighbors_within_radius(1.0).sorted());
 //! assert_eq!(radius_neighbors, vec![&Point { x: 2.0, y: 2.0 }]);
 //! ```
 //! 
@@ -108,17 +108,43 @@
 //! let nearest_neighbor = vp_tree.nearest_neighbor(&search_point);
 //! assert_eq!(nearest_neighbor.unwrap().data, "C".to_string());
 //! 
-//! let k_nearest = vp_tree.querry(&search_point, Querry::k_nearest_neighbors(2).sorted());
+//! let k_nearest = vp_tree.query(&search_point, Query::k_nearest_neighbors(2).sorted());
 //! assert_eq!(k_nearest[0].data, "C".to_string());
 //! 
-//! let radius_neighbors = vp_tree.querry(&search_point, Querry::neighbors_within_radius(1.0).sorted());
+//! let radius_neighbors = vp_tree.query(&search_point, Query::neighbors_within_radius(1.0).sorted());
 //! assert_eq!(radius_neighbors[0].data, "C".to_string());
 //! ```
 
 mod distance;
 mod vp_tree;
-mod querry;
+mod query;
+mod collector;
+mod counter;
+mod pending_index;
+mod search_stats;
+mod tree_stats;
+mod neighborhood;
+mod rebuild_policy;
+mod vp_selection;
+mod payload_tree;
+mod ordered_tree;
+mod in_order_tree;
+pub mod metrics;
+#[cfg(feature = "testing")]
+pub mod testing;
 
 pub use distance::Distance;
-pub use vp_tree::VpTree;
-pub use querry::Querry;
\ No newline at end of file
+pub use vp_tree::{NodeId, TrackedItemsMut, VpTree};
+pub use query::Query;
+
+/// Deprecated spelling of [`Query`].
+#[deprecated(note = "renamed to `Query`")]
+pub type Querry = Query;
+pub use search_stats::SearchStats;
+pub use tree_stats::TreeStats;
+pub use neighborhood::Neighborhood;
+pub use rebuild_policy::RebuildPolicy;
+pub use vp_selection::VpSelection;
+pub use payload_tree::VpTreeWithPayload;
+pub use ordered_tree::{OrdKey, VpTreeOrdered};
+pub use in_order_tree::VpTreeInOrder;
\ No newline at end of file
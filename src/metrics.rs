@@ -0,0 +1,393 @@
+//! Ready-made [`Distance`] implementations for common metrics.
+//! 
+//! The metrics are implemented on wrapper types, the wrapped data is accessible through public fields or accessors where it has to stay valid.
+
+use std::sync::Arc;
+
+use crate::{Distance, VpTree};
+
+/// Byte array compared using the Hamming distance, the number of differing bits.
+/// 
+/// Useful for near-duplicate detection over binary fingerprints such as SimHash.
+/// 
+/// ## Example
+/// ```rust
+/// use vp_tree::{VpTree, Query, metrics::Hamming};
+/// 
+/// let fingerprints = vec![Hamming([0b0000_0000u8; 4]), Hamming([0b0000_0011; 4]), Hamming([0b1111_1111; 4])];
+/// let vp_tree = VpTree::new(fingerprints);
+/// 
+/// let near_duplicates = vp_tree.query(&Hamming([0b0000_0001; 4]), Query::neighbors_within_radius(4.0));
+/// assert_eq!(near_duplicates.len(), 2);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Hamming<const N: usize>(pub [u8; N]);
+
+impl<const N: usize> Distance<Hamming<N>> for Hamming<N> {
+    fn distance(&self, other: &Hamming<N>) -> f64 {
+        self.0.iter().zip(other.0.iter())
+            .map(|(a, b)| (a ^ b).count_ones())
+            .sum::<u32>() as f64
+    }
+}
+
+/// Metric used to compare the rows of a flat coordinate buffer, see [`FlatPoint`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Metric {
+    /// Euclidean distance `sqrt(sum((a_i - b_i)^2))`.
+    #[default]
+    Euclidean,
+    /// Manhattan distance `sum(|a_i - b_i|)`.
+    Manhattan,
+    /// Chebyshev distance `max(|a_i - b_i|)`.
+    Chebyshev,
+}
+
+impl Metric {
+    /// Distance between two coordinate vectors of equal length, squared for the Euclidean metric.
+    fn heuristic(self, a: &[f64], b: &[f64]) -> f64 {
+        debug_assert_eq!(a.len(), b.len(), "vectors of different dimensionality");
+        let diffs = a.iter().zip(b.iter()).map(|(a, b)| (a - b).abs());
+        match self {
+            Metric::Euclidean => diffs.map(|diff| diff * diff).sum(),
+            Metric::Manhattan => diffs.sum(),
+            Metric::Chebyshev => diffs.fold(0.0, f64::max),
+        }
+    }
+
+    /// Converts a heuristic computed by [`Self::heuristic`] into the distance.
+    fn distance(self, heuristic: f64) -> f64 {
+        match self {
+            Metric::Euclidean => heuristic.sqrt(),
+            Metric::Manhattan | Metric::Chebyshev => heuristic,
+        }
+    }
+}
+
+/// Row of a flat, row-major coordinate buffer compared using its [`Metric`], created by [`VpTree::from_flat`].
+/// 
+/// All rows of a tree share the buffer and the metric, a row only stores its position. The coordinates are accessible through [`Self::coords`].
+/// Coordinate slices `&[f64]` of the same length can be used as query targets, they are compared using the metric of the rows.
+/// 
+/// ## Example
+/// ```rust
+/// use vp_tree::{VpTree, Query, metrics::Metric};
+/// 
+/// // Three points in 2D, one row per point
+/// let coords = vec![0.0, 0.0, 5.0, 5.0, 1.0, 2.0];
+/// let vp_tree = VpTree::from_flat(coords, 2, Metric::Manhattan);
+/// 
+/// let target: &[f64] = &[1.0, 1.0];
+/// let nearest = vp_tree.nearest_neighbor(&target).unwrap();
+/// assert_eq!(nearest.coords(), [1.0, 2.0]);
+/// assert_eq!(nearest.row(), 2);
+/// ```
+#[derive(Clone)]
+pub struct FlatPoint {
+    buffer: Arc<FlatBuffer>,
+    row: usize,
+}
+
+/// Coordinate buffer and metric shared by all [`FlatPoint`]s of a tree.
+struct FlatBuffer {
+    coords: Vec<f64>,
+    dim: usize,
+    metric: Metric,
+}
+
+impl FlatPoint {
+    /// Returns the coordinates of the row.
+    pub fn coords(&self) -> &[f64] {
+        let start = self.row * self.buffer.dim;
+        &self.buffer.coords[start..start + self.buffer.dim]
+    }
+
+    /// Returns the index of the row in the buffer passed to [`VpTree::from_flat`].
+    pub fn row(&self) -> usize {
+        self.row
+    }
+
+    /// Returns the metric the row is compared with.
+    pub fn metric(&self) -> Metric {
+        self.buffer.metric
+    }
+}
+
+impl std::fmt::Debug for FlatPoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FlatPoint")
+            .field("row", &self.row)
+            .field("coords", &self.coords())
+            .field("metric", &self.metric())
+            .finish()
+    }
+}
+
+impl PartialEq for FlatPoint {
+    fn eq(&self, other: &FlatPoint) -> bool {
+        self.coords() == other.coords() && self.metric() == other.metric()
+    }
+}
+
+impl Distance<FlatPoint> for FlatPoint {
+    fn distance(&self, other: &FlatPoint) -> f64 {
+        self.metric().distance(self.distance_heuristic(other))
+    }
+
+    fn distance_heuristic(&self, other: &FlatPoint) -> f64 {
+        self.metric().heuristic(self.coords(), other.coords())
+    }
+}
+
+impl Distance<FlatPoint> for &[f64] {
+    fn distance(&self, other: &FlatPoint) -> f64 {
+        other.metric().distance(self.distance_heuristic(other))
+    }
+
+    fn distance_heuristic(&self, other: &FlatPoint) -> f64 {
+        other.metric().heuristic(self, other.coords())
+    }
+}
+
+impl VpTree<FlatPoint> {
+    /// Constructs a new [`VpTree`] from a flat, row-major buffer of `coords.len() / dim` points with `dim` coordinates each.
+    /// All points are compared using `metric`. The buffer is kept as is and shared by the rows. This constructor uses a single thread.
+    /// 
+    /// Panics if `dim` is zero or the length of `coords` is not a multiple of `dim`.
+    pub fn from_flat(coords: Vec<f64>, dim: usize, metric: Metric) -> Self {
+        assert!(dim > 0, "dim must be greater than zero");
+        assert!(coords.len().is_multiple_of(dim), "the length of coords must be a multiple of dim");
+
+        let rows = coords.len() / dim;
+        let buffer = Arc::new(FlatBuffer { coords, dim, metric });
+        let points = (0..rows)
+            .map(|row| FlatPoint { buffer: Arc::clone(&buffer), row })
+            .collect();
+        VpTree::new(points)
+    }
+}
+
+/// Geographic coordinate in degrees, compared using the great-circle distance in meters.
+/// 
+/// The distance is computed with the haversine formula on a sphere with the mean earth radius, which satisfies the triangle inequality.
+/// Requires the `geo` feature.
+/// 
+/// ## Example
+/// ```rust
+/// use vp_tree::{VpTree, Query, metrics::GeoPoint};
+/// 
+/// let cities = vec![
+///     GeoPoint { lat: 52.5200, lon: 13.4050 }, // Berlin
+///     GeoPoint { lat: 52.3906, lon: 13.0645 }, // Potsdam
+///     GeoPoint { lat: 48.1351, lon: 11.5820 }, // Munich
+/// ];
+/// let vp_tree = VpTree::new(cities);
+/// 
+/// let within_50km = vp_tree.query(&GeoPoint { lat: 52.5200, lon: 13.4050 }, Query::neighbors_within_radius(50_000.0));
+/// assert_eq!(within_50km.len(), 2);
+/// ```
+#[cfg(feature = "geo")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoPoint {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+#[cfg(feature = "geo")]
+impl GeoPoint {
+    /// Mean earth radius in meters.
+    pub const EARTH_RADIUS: f64 = 6_371_008.8;
+}
+
+#[cfg(feature = "geo")]
+impl Distance<GeoPoint> for GeoPoint {
+    fn distance(&self, other: &GeoPoint) -> f64 {
+        2.0 * Self::EARTH_RADIUS * self.distance_heuristic(other).sqrt().min(1.0).asin()
+    }
+
+    /// Haversine of the central angle, monotonic in the great-circle distance.
+    fn distance_heuristic(&self, other: &GeoPoint) -> f64 {
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let sin_lat = ((lat2 - lat1) / 2.0).sin();
+        let sin_lon = ((other.lon - self.lon).to_radians() / 2.0).sin();
+        sin_lat * sin_lat + lat1.cos() * lat2.cos() * sin_lon * sin_lon
+    }
+}
+
+/// [f32] coordinate vector compared using the Euclidean distance, for example `Euclidean<Vec<f32>>` or `Euclidean<[f32; N]>`.
+/// 
+/// Squared differences are accumulated in [f64], avoiding the precision loss of f32 accumulation in high dimensions.
+/// Requires the `euclidean` feature.
+/// 
+/// ## Example
+/// ```rust
+/// use vp_tree::{VpTree, metrics::Euclidean};
+/// 
+/// let embeddings = vec![Euclidean([0.0f32, 0.0, 1.0]), Euclidean([0.0, 1.0, 0.0]), Euclidean([1.0, 0.0, 0.0])];
+/// let vp_tree = VpTree::new(embeddings);
+/// 
+/// let nearest = vp_tree.nearest_neighbor(&Euclidean([0.9, 0.1, 0.0])).unwrap();
+/// assert_eq!(nearest.0, [1.0, 0.0, 0.0]);
+/// ```
+#[cfg(feature = "euclidean")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Euclidean<V>(pub V);
+
+#[cfg(feature = "euclidean")]
+impl<V: AsRef<[f32]>> Distance<Euclidean<V>> for Euclidean<V> {
+    fn distance(&self, other: &Euclidean<V>) -> f64 {
+        self.distance_heuristic(other).sqrt()
+    }
+
+    /// Squared Euclidean distance.
+    fn distance_heuristic(&self, other: &Euclidean<V>) -> f64 {
+        let (a, b) = (self.0.as_ref(), other.0.as_ref());
+        debug_assert_eq!(a.len(), b.len(), "vectors of different dimensionality");
+        a.iter().zip(b.iter())
+            .map(|(&a, &b)| {
+                let diff = a as f64 - b as f64;
+                diff * diff
+            })
+            .sum()
+    }
+}
+
+/// [f64] coordinate vector compared using the Euclidean distance, for vectors whose length is only known at runtime such as `EuclideanF64<Vec<f64>>`.
+/// 
+/// All vectors in a tree have to have the same length. Debug builds panic on a mismatch, for example from a malformed row in the input data.
+/// Requires the `euclidean` feature.
+/// 
+/// ## Example
+/// ```rust
+/// use vp_tree::{VpTree, metrics::EuclideanF64};
+/// 
+/// let rows = vec![EuclideanF64(vec![0.0, 0.0, 1.0]), EuclideanF64(vec![0.0, 1.0, 0.0]), EuclideanF64(vec![1.0, 0.0, 0.0])];
+/// let vp_tree = VpTree::new(rows);
+/// 
+/// let nearest = vp_tree.nearest_neighbor(&EuclideanF64(vec![0.9, 0.1, 0.0])).unwrap();
+/// assert_eq!(nearest.0, vec![1.0, 0.0, 0.0]);
+/// ```
+#[cfg(feature = "euclidean")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EuclideanF64<V>(pub V);
+
+#[cfg(feature = "euclidean")]
+impl<V: AsRef<[f64]>> Distance<EuclideanF64<V>> for EuclideanF64<V> {
+    fn distance(&self, other: &EuclideanF64<V>) -> f64 {
+        self.distance_heuristic(other).sqrt()
+    }
+
+    /// Squared Euclidean distance.
+    fn distance_heuristic(&self, other: &EuclideanF64<V>) -> f64 {
+        let (a, b) = (self.0.as_ref(), other.0.as_ref());
+        debug_assert_eq!(a.len(), b.len(), "vectors of different dimensionality");
+        a.iter().zip(b.iter())
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum()
+    }
+}
+
+/// Point compared using a weighted Euclidean distance `sqrt(sum(w_i * (a_i - b_i)^2))`, for axes with different scales.
+/// 
+/// All points in a tree have to use the same non-negative `weights`, which are checked by [`Self::new`]. Requires the `euclidean` feature.
+/// 
+/// ## Example
+/// ```rust
+/// use vp_tree::{VpTree, metrics::ScaledEuclidean};
+/// 
+/// // Meters and seconds, one second weighs like 10 meters
+/// let weights = [1.0, 100.0];
+/// let events = vec![
+///     ScaledEuclidean::new([0.0, 0.0], weights),
+///     ScaledEuclidean::new([5.0, 1.0], weights),
+///     ScaledEuclidean::new([8.0, 0.0], weights),
+/// ];
+/// let vp_tree = VpTree::new(events);
+/// 
+/// let nearest = vp_tree.nearest_neighbor(&ScaledEuclidean::new([5.0, 0.0], weights)).unwrap();
+/// assert_eq!(nearest.coords, [8.0, 0.0]);
+/// ```
+#[cfg(feature = "euclidean")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScaledEuclidean<const D: usize> {
+    pub coords: [f64; D],
+    weights: [f64; D],
+}
+
+#[cfg(feature = "euclidean")]
+impl<const D: usize> ScaledEuclidean<D> {
+    /// Creates a point with the given coordinates and axis weights.
+    /// 
+    /// Panics if a weight is negative or NaN, which does not result in a metric.
+    pub fn new(coords: [f64; D], weights: [f64; D]) -> Self {
+        assert!(weights.iter().all(|w| *w >= 0.0), "weights must be non-negative");
+        ScaledEuclidean { coords, weights }
+    }
+
+    /// Returns the axis weights of the point.
+    pub fn weights(&self) -> [f64; D] {
+        self.weights
+    }
+}
+
+#[cfg(feature = "euclidean")]
+impl<const D: usize> Distance<ScaledEuclidean<D>> for ScaledEuclidean<D> {
+    fn distance(&self, other: &ScaledEuclidean<D>) -> f64 {
+        self.distance_heuristic(other).sqrt()
+    }
+
+    /// Squared weighted Euclidean distance.
+    fn distance_heuristic(&self, other: &ScaledEuclidean<D>) -> f64 {
+        (0..D)
+            .map(|i| {
+                let diff = self.coords[i] - other.coords[i];
+                self.weights[i] * diff * diff
+            })
+            .sum()
+    }
+}
+
+/// Point in a periodic box (a torus), compared using the Euclidean distance under the minimum image convention.
+/// 
+/// Along every axis, the difference wraps around the box, using the shorter of `|dx|` and `box_size - |dx|`.
+/// All points in a tree have to use the same `box_size`. Requires the `periodic` feature.
+/// 
+/// ## Example
+/// ```rust
+/// use vp_tree::{VpTree, metrics::PeriodicEuclidean};
+/// 
+/// let box_size = [10.0, 10.0];
+/// let particles = vec![
+///     PeriodicEuclidean { coords: [0.5, 5.0], box_size },
+///     PeriodicEuclidean { coords: [5.0, 5.0], box_size },
+/// ];
+/// let vp_tree = VpTree::new(particles);
+/// 
+/// // Across the boundary, the particle at x = 0.5 is only 1.0 away
+/// let nearest = vp_tree.nearest_neighbor(&PeriodicEuclidean { coords: [9.5, 5.0], box_size }).unwrap();
+/// assert_eq!(nearest.coords, [0.5, 5.0]);
+/// ```
+#[cfg(feature = "periodic")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PeriodicEuclidean<const D: usize> {
+    pub coords: [f64; D],
+    pub box_size: [f64; D],
+}
+
+#[cfg(feature = "periodic")]
+impl<const D: usize> Distance<PeriodicEuclidean<D>> for PeriodicEuclidean<D> {
+    fn distance(&self, other: &PeriodicEuclidean<D>) -> f64 {
+        self.distance_heuristic(other).sqrt()
+    }
+
+    /// Squared minimum image distance.
+    fn distance_heuristic(&self, other: &PeriodicEuclidean<D>) -> f64 {
+        (0..D)
+            .map(|i| {
+                let dx = (self.coords[i] - other.coords[i]).rem_euclid(self.box_size[i]);
+                let dx = dx.min(self.box_size[i] - dx);
+                dx * dx
+            })
+            .sum()
+    }
+}
@@ -0,0 +1,10 @@
+/// The nearest other items of a stored item, as used by the Local Outlier Factor. Returned by [`crate::VpTree::lof_neighborhoods`].
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Neighborhood {
+    /// Indices of the nearest other items, closest first. Indices refer to the order of [`crate::VpTree::items`].
+    pub indices: Vec<usize>,
+    /// Distances to the items in [`Self::indices`].
+    pub distances: Vec<f64>,
+    /// Distance to the farthest neighbor, the k-distance of the item. [`f64::INFINITY`] if the item has no neighbors.
+    pub kth_distance: f64,
+}
@@ -0,0 +1,136 @@
+use std::{borrow::Borrow, collections::BinaryHeap};
+
+use crate::{Distance, Query, VpTree, collector::{OrderedHeapItem, OrderedKnnCollector}};
+
+/// Secondary key used by [`VpTreeOrdered`] to break ties between items at the same distance.
+pub trait OrdKey {
+    /// Key type of the items. Among equidistant items, the ones with the higher key are preferred.
+    type Key: Ord;
+
+    /// Returns the key of the item.
+    fn ord_key(&self) -> Self::Key;
+}
+
+/// A [`VpTree`] whose queries break distance ties deterministically by the [`OrdKey`] of the items.
+/// 
+/// When a query is limited to `k` items and several items are tied at the distance of the `k`-th nearest,
+/// the items with the highest key are kept. Sorted results list equidistant items by descending key.
+/// 
+/// ## Example
+/// ```rust
+/// use vp_tree::{Distance, OrdKey, Query, VpTreeOrdered};
+/// 
+/// struct Point {
+///     x: f64,
+///     priority: u32,
+/// }
+/// 
+/// impl Distance<Point> for Point {
+///     fn distance(&self, other: &Point) -> f64 {
+///         (self.x - other.x).abs()
+///     }
+/// }
+/// 
+/// impl OrdKey for Point {
+///     type Key = u32;
+/// 
+///     fn ord_key(&self) -> u32 {
+///         self.priority
+///     }
+/// }
+/// 
+/// let points = vec![Point { x: -1.0, priority: 1 }, Point { x: 1.0, priority: 3 }, Point { x: 5.0, priority: 9 }];
+/// let vp_tree = VpTreeOrdered::new(points);
+/// 
+/// let nearest = vp_tree.query(&Point { x: 0.0, priority: 0 }, Query::k_nearest_neighbors(1));
+/// assert_eq!(nearest[0].priority, 3);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct VpTreeOrdered<T> {
+    tree: VpTree<T>,
+}
+
+impl<T: Distance<T> + OrdKey> VpTreeOrdered<T> {
+    /// Constructs a new [`VpTreeOrdered`] from a vector of items. See [`VpTree::new`].
+    pub fn new(items: Vec<T>) -> Self {
+        VpTreeOrdered { tree: VpTree::new(items) }
+    }
+
+    /// Constructs a new [`VpTreeOrdered`] using multiple threads. See [`VpTree::new_parallel`].
+    pub fn new_parallel(items: Vec<T>, threads: usize) -> Self
+    where
+        T: Send,
+    {
+        VpTreeOrdered { tree: VpTree::new_parallel(items, threads) }
+    }
+
+    /// Performs a query on the tree using the specified target and query parameters.
+    /// Ties in distance are broken by the [`OrdKey`] of the items, preferring higher keys.
+    pub fn query<U, Q>(&self, target: &U, query: Q) -> Vec<&T>
+    where
+        U: Distance<T>,
+        Q: Borrow<Query>,
+    {
+        let query = query.borrow();
+        let heap = if query.collapse_duplicates {
+            self.search_collapsed(target, query)
+        } else {
+            self.search(target, query)
+        };
+
+        let heap = if query.sorted {
+            heap.into_sorted_vec()
+        } else {
+            heap.into_vec()
+        };
+
+        heap.into_iter()
+            .map(|item| &self.tree.items()[item.index])
+            .collect()
+    }
+
+    /// Returns the underlying [`VpTree`].
+    pub fn tree(&self) -> &VpTree<T> {
+        &self.tree
+    }
+
+    /// Consumes the tree and returns the underlying [`VpTree`].
+    pub fn into_tree(self) -> VpTree<T> {
+        self.tree
+    }
+
+    fn search<U: Distance<T>>(&self, target: &U, query: &Query) -> BinaryHeap<OrderedHeapItem<T::Key>> {
+        let mut collector = OrderedKnnCollector::new(query, self.tree.items());
+        self.tree.search_with(target, &mut collector);
+        collector.heap
+    }
+
+    /// Searches twice as many candidates until enough distinct items remain after removing duplicates, or all candidates are found.
+    /// Of several duplicates, the one with the highest key is kept.
+    fn search_collapsed<U: Distance<T>>(&self, target: &U, query: &Query) -> BinaryHeap<OrderedHeapItem<T::Key>> {
+        let mut candidates = Query { collapse_duplicates: false, ..query.clone() };
+
+        loop {
+            let items = self.search(target, &candidates).into_sorted_vec();
+            let found = items.len();
+            let mut distinct: Vec<OrderedHeapItem<T::Key>> = Vec::new();
+            for item in items {
+                if distinct.len() == query.max_items {
+                    break;
+                }
+                let duplicate = distinct.iter()
+                    .rev()
+                    .take_while(|kept| kept.distance == item.distance)
+                    .any(|kept| self.tree.items()[kept.index].distance(&self.tree.items()[item.index]) == 0.0);
+                if !duplicate {
+                    distinct.push(item);
+                }
+            }
+
+            if distinct.len() == query.max_items || found < candidates.max_items {
+                return distinct.into();
+            }
+            candidates.max_items = candidates.max_items.saturating_mul(2);
+        }
+    }
+}
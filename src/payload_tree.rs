@@ -0,0 +1,139 @@
+use std::borrow::Borrow;
+
+use crate::{Distance, Query, VpTree};
+
+/// A [`VpTree`] over coordinates with a separately stored payload for every coordinate.
+/// 
+/// Searches only touch the contiguous coordinate storage, keeping large payloads out of the cache during traversal.
+/// Results are returned as pairs of coordinate and payload.
+/// 
+/// ## Example
+/// ```rust
+/// use vp_tree::{Distance, Query, VpTreeWithPayload};
+/// 
+/// struct Point {
+///     x: f64,
+///     y: f64,
+/// }
+/// 
+/// impl Distance<Point> for Point {
+///     fn distance(&self, other: &Point) -> f64 {
+///         ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+///     }
+/// }
+/// 
+/// let coords = vec![Point { x: 0.0, y: 0.0 }, Point { x: 1.0, y: 1.0 }, Point { x: 2.0, y: 2.0 }];
+/// let payloads = vec!["A", "B", "C"];
+/// 
+/// let vp_tree = VpTreeWithPayload::new(coords, payloads);
+/// 
+/// let (_, payload) = vp_tree.nearest_neighbor(&Point { x: 1.9, y: 1.8 }).unwrap();
+/// assert_eq!(*payload, "C");
+/// 
+/// let k_nearest = vp_tree.query(&Point { x: 0.1, y: 0.0 }, Query::k_nearest_neighbors(2).sorted());
+/// assert_eq!(k_nearest.iter().map(|(_, payload)| **payload).collect::<Vec<_>>(), vec!["A", "B"]);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct VpTreeWithPayload<C, P> {
+    tree: VpTree<C>,
+    payloads: Vec<P>,
+}
+
+impl<C: Distance<C>, P> VpTreeWithPayload<C, P> {
+    /// Constructs a new [`VpTreeWithPayload`] from coordinates and their payloads. The payload at index `i` belongs to the coordinate at index `i`.
+    /// 
+    /// Panics if `coords` and `payloads` have different lengths.
+    pub fn new(coords: Vec<C>, payloads: Vec<P>) -> Self {
+        assert_eq!(coords.len(), payloads.len(), "coords and payloads must have the same length");
+
+        let indexed = coords.into_iter()
+            .enumerate()
+            .map(|(index, item)| Indexed { item, index })
+            .collect::<Vec<_>>();
+
+        let tree = VpTree::new(indexed);
+        Self::from_indexed(tree, payloads)
+    }
+
+    /// Constructs a new [`VpTreeWithPayload`] using multiple threads. See [`VpTree::new_parallel`].
+    /// 
+    /// Panics if `coords` and `payloads` have different lengths.
+    pub fn new_parallel(coords: Vec<C>, payloads: Vec<P>, threads: usize) -> Self
+    where
+        C: Send,
+    {
+        assert_eq!(coords.len(), payloads.len(), "coords and payloads must have the same length");
+
+        let indexed = coords.into_iter()
+            .enumerate()
+            .map(|(index, item)| Indexed { item, index })
+            .collect::<Vec<_>>();
+
+        let tree = VpTree::new_parallel(indexed, threads);
+        Self::from_indexed(tree, payloads)
+    }
+
+    /// Performs a query on the coordinates using the specified target and query parameters.
+    /// Returns the coordinates and payloads of the items that match the query criteria.
+    pub fn query<U, Q>(&self, target: &U, query: Q) -> Vec<(&C, &P)>
+    where
+        U: Distance<C>,
+        Q: Borrow<Query>,
+    {
+        self.tree.search_items(target, query.borrow())
+            .into_iter()
+            .map(|item| (&self.tree.items()[item.index], &self.payloads[item.index]))
+            .collect()
+    }
+
+    /// Searches for the single nearest neighbor to the target. See [`VpTree::nearest_neighbor`].
+    pub fn nearest_neighbor<U: Distance<C>>(&self, target: &U) -> Option<(&C, &P)> {
+        self.tree.search_nearest(target, false)
+            .map(|index| (&self.tree.items()[index], &self.payloads[index]))
+    }
+
+    /// Returns the underlying [`VpTree`] over the coordinates.
+    pub fn tree(&self) -> &VpTree<C> {
+        &self.tree
+    }
+
+    /// Returns the payloads, in the same order as the items of [`Self::tree`].
+    pub fn payloads(&self) -> &[P] {
+        &self.payloads
+    }
+
+    /// Consumes the tree and returns the coordinates and payloads. The order is arbitrary but matches between both vectors.
+    pub fn into_parts(self) -> (Vec<C>, Vec<P>) {
+        (self.tree.into_items(), self.payloads)
+    }
+
+    fn from_indexed(tree: VpTree<Indexed<C>>, payloads: Vec<P>) -> Self {
+        let order = tree.items().iter().map(|item| item.index).collect::<Vec<_>>();
+
+        let mut payloads = payloads.into_iter().map(Some).collect::<Vec<_>>();
+        let payloads = order.into_iter()
+            .map(|index| payloads[index].take().unwrap())
+            .collect();
+
+        VpTreeWithPayload {
+            tree: tree.map_items(|indexed| indexed.item),
+            payloads,
+        }
+    }
+}
+
+/// Item tagged with its position in the input of a constructor, to track the permutation applied while building.
+pub(crate) struct Indexed<C> {
+    pub(crate) item: C,
+    pub(crate) index: usize,
+}
+
+impl<C: Distance<C>> Distance<Indexed<C>> for Indexed<C> {
+    fn distance(&self, other: &Indexed<C>) -> f64 {
+        self.item.distance(&other.item)
+    }
+
+    fn distance_heuristic(&self, other: &Indexed<C>) -> f64 {
+        self.item.distance_heuristic(&other.item)
+    }
+}
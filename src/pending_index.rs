@@ -0,0 +1,25 @@
+use std::sync::OnceLock;
+
+use crate::VpTree;
+
+/// Index over the items appended to a [`VpTree`] since its last rebuild, built by the first search after [`VpTree::append`].
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PendingIndex {
+    /// Number of unindexed items covered by the index, starting at the first unindexed item.
+    pub(crate) len: usize,
+    /// Tree over the offsets of the covered items from the first unindexed item.
+    pub(crate) tree: OnceLock<Box<VpTree<usize>>>,
+}
+
+impl PendingIndex {
+    pub(crate) fn new(len: usize) -> Self {
+        PendingIndex { len, tree: OnceLock::new() }
+    }
+}
+
+/// The index is derived from the items and does not affect equality.
+impl PartialEq for PendingIndex {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
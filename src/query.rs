@@ -0,0 +1,137 @@
+
+/// Query parameters for searching the VpTree.
+#[derive(Debug, Clone)]
+pub struct Query {
+    pub (crate) max_items: usize,
+    pub (crate) max_distance: f64,
+    pub (crate) exclusive: bool,
+    pub (crate) sorted: bool,
+    pub (crate) exclusive_boundary: bool,
+    pub (crate) collapse_duplicates: bool,
+    pub (crate) boundary_epsilon: f64,
+}
+
+impl Default for Query {
+    /// Create a new Query that returns all items. Query can be restricted using the builder methods.
+    fn default() -> Self {
+        Query {
+            max_items: usize::MAX,
+            max_distance: f64::INFINITY,
+            exclusive: false,
+            sorted: false,
+            exclusive_boundary: false,
+            collapse_duplicates: false,
+            boundary_epsilon: 0.0,
+        }
+    }
+}
+
+impl Query {
+    /// Create a new Query with the given parameters.
+    /// ## Parameters
+    /// - `max_items`: Maximum number of items to return. The nearest items are returned.
+    /// - `max_distance`: Maximum distance for items to be included in the query.
+    /// - `exclusive`: Whether the query should be exclusive (exclude items with distance zero).
+    /// - `sorted`: Whether the returned items should be sorted by distance (closest first).
+    pub fn new(max_items: usize, max_distance: f64, exclusive: bool, sorted: bool) -> Self {
+        assert!(max_items > 0, "max_items must be greater than zero");
+        assert!(max_distance >= 0.0, "max_distance must be non-negative");
+        Query {
+            max_items,
+            max_distance,
+            exclusive,
+            sorted,
+            exclusive_boundary: false,
+            collapse_duplicates: false,
+            boundary_epsilon: 0.0,
+        }
+    }
+
+    /// Create a Query for k-nearest neighbors.
+    pub fn k_nearest_neighbors(max_items: usize) -> Self {
+        Query::new(max_items, f64::INFINITY, false, false)
+    }
+
+    /// Create a Query for k-nearest neighbors within a given radius.
+    pub fn k_nearest_neighbors_within_radius(max_items: usize, max_distance: f64) -> Self {
+        Query::new(max_items, max_distance, false, false)
+    }
+
+    /// Create a Query for all neighbors within a given radius.
+    pub fn neighbors_within_radius(max_distance: f64) -> Self {
+        Query::new(usize::MAX, max_distance, false, false)
+    }
+
+    /// Prevents items with distance zero from being included in the results.
+    /// By default, items with distance zero are included.
+    pub fn exclusive(mut self) -> Self {
+        self.exclusive = true;
+        self
+    }
+
+    /// Excludes items at exactly the maximum distance, only items with `distance < max_distance` are included.
+    /// By default, the radius is inclusive (`distance <= max_distance`).
+    /// 
+    /// This controls the outer boundary of the query, while [`Self::exclusive`] controls items at distance zero such as the target itself.
+    pub fn exclusive_boundary(mut self) -> Self {
+        self.exclusive_boundary = true;
+        self
+    }
+
+    /// Keeps only one representative of items at distance zero to each other, such as exact duplicates in the input.
+    /// Duplicates are removed before the `max_items` limit applies, so k-nearest neighbor queries return `k` distinct items if possible.
+    /// 
+    /// Duplicates of an item always have the same distance to the target, only items at equal distance are compared.
+    /// [`crate::VpTree::query_capped`] and [`crate::VpTree::query_with_stats`] ignore this option.
+    pub fn collapse_duplicates(mut self) -> Self {
+        self.collapse_duplicates = true;
+        self
+    }
+
+    /// Explores both subtrees of a node whenever the distance to its vantage point is within `epsilon` of the pruning boundary.
+    /// By default, `epsilon` is zero.
+    /// 
+    /// Pruning relies on distances computed during the search being consistent with the thresholds computed during construction.
+    /// Rounding errors, for example of distances accumulated in [`f32`], can break this at the boundary and cause missed items.
+    /// A small epsilon trades some additional distance calculations for robustness. Which items are returned is unaffected.
+    pub fn boundary_epsilon(mut self, epsilon: f64) -> Self {
+        assert!(epsilon >= 0.0, "epsilon must be non-negative");
+        self.boundary_epsilon = epsilon;
+        self
+    }
+
+    /// Sets the output to be sorted by distance (closest first).
+    /// By default, the output is not sorted.
+    pub fn sorted(mut self) -> Self {
+        self.sorted = true;
+        self
+    }
+
+    /// Sets the maximum distance for items to be included in the results.
+    pub fn within_radius(mut self, max_distance: f64) -> Self {
+        assert!(max_distance >= 0.0, "max_distance must be non-negative");
+        self.max_distance = max_distance;
+        self
+    }
+
+    /// Starts pruning from a known upper bound on the distance of the wanted items, for example from a coarser previous search.
+    /// Subtrees farther than the bound are skipped from the first node on.
+    /// 
+    /// Behaves exactly like [`Self::within_radius`], items beyond the bound are not returned even if fewer items are found.
+    pub fn initial_bound(self, upper_bound: f64) -> Self {
+        self.within_radius(upper_bound)
+    }
+
+    /// Sets the maximum number of items to be returned. The nearest items are returned.
+    pub fn max_items(mut self, max_items: usize) -> Self {
+        assert!(max_items > 0, "max_items must be greater than zero");
+        self.max_items = max_items;
+        self
+    }
+
+    /// Checks the exclusion rules of the query for an item within the maximum distance.
+    #[inline(always)]
+    pub(crate) fn accepts(&self, distance: f64) -> bool {
+        (!self.exclusive || distance > 0.0) && (!self.exclusive_boundary || distance < self.max_distance)
+    }
+}
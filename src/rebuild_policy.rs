@@ -0,0 +1,15 @@
+/// Policy deciding when a [`crate::VpTree`] automatically rebuilds itself after items have been inserted.
+/// 
+/// Inserted items are not part of the tree structure until the next rebuild, every search compares them linearly.
+/// A rebuild takes O(n log n) time and restores the optimal [`crate::VpTree::depth`].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RebuildPolicy {
+    /// Never rebuild automatically. Use [`crate::VpTree::rebuild`] to rebuild manually.
+    #[default]
+    Never,
+    /// Rebuild once the given number of mutations happened since the last rebuild.
+    AfterMutations(usize),
+    /// Rebuild once [`crate::VpTree::depth`] exceeds [`crate::VpTree::expected_depth`] by the given ratio.
+    WhenImbalanceExceeds(f64),
+}
@@ -0,0 +1,9 @@
+/// Statistics about a single search, returned by [`crate::VpTree::query_with_stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SearchStats {
+    /// Number of nodes whose distance to the target was computed, including inserted items that are not yet part of the tree structure.
+    pub nodes_visited: usize,
+    /// Fraction of all items whose distance to the target was computed. Values close to `1.0` indicate that the search could barely prune,
+    /// often caused by a high intrinsic dimensionality of the data.
+    pub fraction_of_tree_visited: f64,
+}
@@ -0,0 +1,52 @@
+//! Testing aids for crates implementing [`Distance`] for their own types. Enabled by the `testing` feature.
+//! 
+//! The helpers compare the results of a [`VpTree`] against a linear scan and panic on mismatches. They are meant for tests only.
+
+use crate::{Distance, Query, VpTree};
+
+/// Asserts that the `k` nearest neighbors of the target found by the tree match a linear scan over `data`.
+/// 
+/// `data` has to contain the same items as the tree, in any order. Results are compared by their distance to the target,
+/// equidistant items may be returned in a different order or replaced by each other.
+/// 
+/// ## Example
+/// ```rust
+/// use vp_tree::{Distance, VpTree, testing::assert_matches_linear};
+/// 
+/// #[derive(Clone)]
+/// struct Point(f64);
+/// 
+/// impl Distance<Point> for Point {
+///     fn distance(&self, other: &Point) -> f64 {
+///         (self.0 - other.0).abs()
+///     }
+/// }
+/// 
+/// let points = (0..100).map(|i| Point(i as f64 * 1.5)).collect::<Vec<_>>();
+/// let vp_tree = VpTree::new(points.clone());
+/// 
+/// assert_matches_linear(&vp_tree, &points, &Point(42.0), 5);
+/// ```
+pub fn assert_matches_linear<T, U>(tree: &VpTree<T>, data: &[T], target: &U, k: usize)
+where
+    T: Distance<T>,
+    U: Distance<T>,
+{
+    assert_eq!(tree.items().len(), data.len(), "the tree holds {} items, the data {}", tree.items().len(), data.len());
+
+    let found = tree.query(target, Query::k_nearest_neighbors(k).sorted())
+        .into_iter()
+        .map(|item| target.distance(item))
+        .collect::<Vec<_>>();
+
+    let mut expected = data.iter()
+        .map(|item| target.distance(item))
+        .collect::<Vec<_>>();
+    expected.sort_by(f64::total_cmp);
+    expected.truncate(k);
+
+    assert_eq!(found.len(), expected.len(), "the tree found {} neighbors, a linear scan {}", found.len(), expected.len());
+    for (rank, (found, expected)) in found.iter().zip(&expected).enumerate() {
+        assert!(found == expected, "neighbor {rank} is at distance {found} in the tree, but at {expected} in a linear scan");
+    }
+}
@@ -0,0 +1,19 @@
+/// Summary of the structure of a [`crate::VpTree`], returned by [`crate::VpTree::stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TreeStats {
+    /// Number of stored items, including inserted items that are not yet part of the tree structure.
+    pub len: usize,
+    /// Number of distance calculations on the longest search path, see [`crate::VpTree::depth`].
+    pub depth: usize,
+    /// Depth of a freshly built tree with the same number of items, see [`crate::VpTree::expected_depth`].
+    pub expected_depth: usize,
+    /// Ratio of [`Self::depth`] to [`Self::expected_depth`], `1.0` for a freshly built tree. Large values indicate that a rebuild speeds up searches.
+    /// Compared against the ratio of [`crate::RebuildPolicy::WhenImbalanceExceeds`].
+    pub imbalance_ratio: f64,
+    /// Number of leaves of the tree structure.
+    pub leaf_count: usize,
+    /// Average number of nodes on the path from the root to a leaf, including the leaf. `0.0` if the tree structure is empty.
+    pub avg_leaf_depth: f64,
+    /// Threshold of the root node, `None` if the tree structure is empty.
+    pub root_threshold: Option<f64>,
+}
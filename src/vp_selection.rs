@@ -0,0 +1,11 @@
+/// Strategy used to select the vantage point of each node while building a [`crate::VpTree`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VpSelection {
+    /// Selects a random item as vantage point. Fastest to build.
+    #[default]
+    Random,
+    /// Samples `sqrt(n)` candidates and selects the one with the largest variance of distances to a small random sample of items.
+    /// Such candidates tend to lie at the boundary of the data, resulting in thinner shells and better pruning during search at the cost of a slower build.
+    Corner,
+}
@@ -1,6 +1,12 @@
-use std::{borrow::Borrow, collections::BinaryHeap, vec};
+use std::{borrow::Borrow, collections::{BinaryHeap, HashMap, HashSet}, hash::Hash, mem::MaybeUninit, ops::ControlFlow, sync::{OnceLock, atomic::{AtomicUsize, Ordering}}, vec};
 
-use crate::{Distance, Querry};
+use crate::{Distance, Neighborhood, Query, RebuildPolicy, SearchStats, TreeStats, VpSelection, collector::{AllNearestCollector, BudgetCollector, Collector, ExistsCollector, HeapItem, KnnCollector, NearestBeyondCollector, NearestCollector, OffsetCollector, ScanCollector, StatsCollector}, counter::DistanceCounter, payload_tree::Indexed, pending_index::PendingIndex};
+#[cfg(feature = "inline-knn")]
+use crate::collector::InlineKnnCollector;
+
+/// Largest number of items a query may request to use the allocation free [`InlineKnnCollector`].
+#[cfg(feature = "inline-knn")]
+const INLINE_KNN_LEN: usize = 16;
 
 /// Vantage-Point Tree (VP-Tree) implementation for efficient nearest neighbor search and radius searches.
 /// Requires stored elements to implement the [`Distance`] trait to themselves.
@@ -12,46 +18,190 @@ use crate::{Distance, Querry};
 /// 
 /// 
 /// The tree takes 8 bytes of memory per stored element for the distance thresholds, plus the memory required to store the elements themselves.
+/// 
+/// All search methods take `&self`, so a built tree can be shared between threads using an [`std::sync::Arc`] whenever `T: Sync`.
+/// Parallel construction additionally requires `T: Send` since items are moved between threads, parallel searches such as
+/// [`Self::knn_graph_parallel`] only require `T: Sync`. Items that are `Sync` but not `Send` can be built with [`Self::new`] and searched from multiple threads.
+/// To periodically replace a shared tree, build the new tree separately and swap the `Arc` (for example inside a `RwLock<Arc<VpTree<T>>>`).
+/// Readers holding a clone of the old `Arc` keep using the old snapshot until they drop it.
+/// 
+/// With the `serde` feature, the tree implements `Serialize` and `Deserialize`. A deserialized tree is used as stored, without rebuilding it.
+/// Deserializing fails if there are more thresholds than items. The count of mutations since the last rebuild is not stored.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(try_from = "VpTreeParts<T>"))]
 pub struct VpTree<T> {
     items: Vec<T>,
     nodes: Vec<f64>,
+    #[cfg(feature = "compact-thresholds")]
+    compact_nodes: Option<Box<[f32]>>,
+    rebuild_policy: RebuildPolicy,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    mutations: usize,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    modified: Vec<usize>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pending: PendingIndex,
+    selection: VpSelection,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    distance_calls: DistanceCounter,
+}
+
+/// Serialized fields of a [`VpTree`], checked before they are used as a tree.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(rename = "VpTree")]
+struct VpTreeParts<T> {
+    items: Vec<T>,
+    nodes: Vec<f64>,
+    #[cfg(feature = "compact-thresholds")]
+    compact_nodes: Option<Box<[f32]>>,
+    rebuild_policy: RebuildPolicy,
+    selection: VpSelection,
+}
+
+#[cfg(feature = "serde")]
+impl<T> TryFrom<VpTreeParts<T>> for VpTree<T> {
+    type Error = String;
+
+    fn try_from(parts: VpTreeParts<T>) -> Result<Self, String> {
+        #[cfg(feature = "compact-thresholds")]
+        if let Some(compact) = &parts.compact_nodes {
+            if !parts.nodes.is_empty() {
+                return Err(format!("a tree with compacted thresholds has {} f64 thresholds, expected none", parts.nodes.len()));
+            }
+            if compact.len() > parts.items.len() {
+                return Err(format!("more thresholds than items: {} thresholds for {} items", compact.len(), parts.items.len()));
+            }
+        }
+        if parts.nodes.len() > parts.items.len() {
+            return Err(format!("more thresholds than items: {} thresholds for {} items", parts.nodes.len(), parts.items.len()));
+        }
+
+        Ok(VpTree {
+            items: parts.items,
+            nodes: parts.nodes,
+            #[cfg(feature = "compact-thresholds")]
+            compact_nodes: parts.compact_nodes,
+            rebuild_policy: parts.rebuild_policy,
+            selection: parts.selection,
+            ..VpTree::default()
+        })
+    }
 }
 
 impl<T: Distance<T>> VpTree<T> {
-    const ROOT: usize = 0;
+    const MIN_PARALLEL_LEN: usize = 1 << 12;
+    const AUTO_PARALLEL_LEN: usize = 1 << 16;
+    const PAGE_SIZE: usize = 1 << 12;
 
     /// Constructs a new [`VpTree`] from a [`Vec`] of items. The items are consumed and stored within the tree. 
     /// This constructor uses a single thread. For parallel construction, use [`Self::new_parallel`].
     pub fn new(mut items: Vec<T>) -> Self {
+        Self::debug_assert_heuristic(&items);
         let mut nodes = vec![0.0; items.len()];
-        Self::build_from_points(&mut items, &mut nodes);
-        VpTree { items, nodes }
+        Self::build_from_points(&mut items, &mut nodes, VpSelection::Random);
+        Self::from_built(items, nodes)
     }   
 
+    /// Constructs a new [`VpTree`] from a [`Vec`] of items, selecting vantage points using the given [`VpSelection`] strategy.
+    /// The items are consumed and stored within the tree. The strategy is also used by later calls to [`Self::rebuild`].
+    pub fn new_with_selection(mut items: Vec<T>, selection: VpSelection) -> Self {
+        Self::debug_assert_heuristic(&items);
+        let mut nodes = vec![0.0; items.len()];
+        Self::build_from_points(&mut items, &mut nodes, selection);
+        VpTree { selection, ..Self::from_built(items, nodes) }
+    }
+
     /// Constructs a new [`VpTree`] from a [`Vec`] of items using multiple threads. The items are consumed and stored within the tree.
     /// The `threads` parameter specifies the number of threads to use for construction. Powers of 2 (2,4,8,16) are recommended for optimal performance. 
     pub fn new_parallel(mut items: Vec<T>, threads: usize) -> Self 
     where
         T: Send,
     {
+        Self::debug_assert_heuristic(&items);
+        let mut nodes = vec![0.0; items.len()];
+        Self::build_from_points_par(&mut items, &mut nodes, threads, VpSelection::Random);
+        Self::from_built(items, nodes)
+    }
+
+    /// Constructs a new [`VpTree`] from a [`Vec`] of items using multiple threads. The items are consumed and stored within the tree.
+    /// At most `max_concurrent_threads` threads, including the calling thread, are building subtrees at the same time.
+    /// Whenever the limit is reached, subtrees are built on the current thread until another thread finishes.
+    /// 
+    /// Unlike [`Self::new_parallel`], uneven subtrees can not spawn more threads than the limit, which prevents oversubscription when multiple trees are built concurrently.
+    pub fn new_parallel_with_limit(mut items: Vec<T>, max_concurrent_threads: usize) -> Self 
+    where
+        T: Send,
+    {
+        Self::debug_assert_heuristic(&items);
         let mut nodes = vec![0.0; items.len()];
-        Self::build_from_points_par(&mut items, &mut nodes, threads);
-        VpTree { items, nodes }
+        let active_threads = AtomicUsize::new(1);
+        Self::build_from_points_limited(&mut items, &mut nodes, &active_threads, max_concurrent_threads, VpSelection::Random);
+        Self::from_built(items, nodes)
+    }
+
+    /// Constructs a new [`VpTree`] from batches of items, for example read from disk in bounded chunks.
+    /// 
+    /// Each chunk is moved into the tree storage as it arrives and freed, and the tree is built once after the last chunk.
+    /// The tree itself holds all items in memory, but the caller never has to materialize them in a separate [`Vec`].
+    pub fn from_chunks<I: IntoIterator<Item = Vec<T>>>(chunks: I) -> Self {
+        let mut tree = Self::default();
+        for chunk in chunks {
+            tree.items.extend(chunk);
+        }
+        tree.rebuild();
+        tree
+    }
+
+    /// Constructs a new [`VpTree`] from an iterator of items, choosing between single-threaded and parallel construction automatically.
+    /// Large inputs are built using [`Self::new_parallel`] with one thread per available core, see [`std::thread::available_parallelism`].
+    pub fn from_iter_auto<I: IntoIterator<Item = T>>(iter: I) -> Self
+    where
+        T: Send,
+    {
+        let items: Vec<T> = iter.into_iter().collect();
+        let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+
+        if items.len() < Self::AUTO_PARALLEL_LEN || threads == 1 {
+            Self::new(items)
+        } else {
+            Self::new_parallel(items, threads)
+        }
+    }
+
+    /// Constructs an empty [`VpTree`] with space for at least `capacity` items and thresholds, to avoid reallocations while inserting.
+    pub fn with_capacity(capacity: usize) -> Self {
+        VpTree {
+            items: Vec::with_capacity(capacity),
+            nodes: Vec::with_capacity(capacity),
+            ..Self::default()
+        }
     }
 
     /// Constructs a new [`VpTree`] from a slice of items, storing references to the original items.
     /// 
-    /// Querrying the tree is faster when storing owned items directly. Use [`Self::new`] or [`Self::new_parallel`] to store owned items.
+    /// Querying the tree is faster when storing owned items directly. Use [`Self::new`] or [`Self::new_parallel`] to store owned items.
     /// Building the tree using references might be faster on datatets with large datapoints. Benchmarking is recommended to determine the best approach for your use case.
     pub fn new_index(items: &[T]) -> VpTree<&T> {
         let items = items.iter().collect::<Vec<&T>>();
         VpTree::<&T>::new(items)
     }
 
+    /// Constructs a new [`VpTree`] storing references to the items, after reordering the caller's items in place into tree order.
+    /// 
+    /// The items stay owned by the caller, no [`std::mem::take`] of a containing field is necessary.
+    /// The tree keeps the mutable borrow for its lifetime, in the meantime the items are accessible through [`Self::items`].
+    /// Unlike [`Self::new_index`], the references are stored in the same order as the items, so searches access the items sequentially.
+    pub fn new_index_in_place(items: &mut [T]) -> VpTree<&T> {
+        Self::debug_assert_heuristic(items);
+        let mut nodes = vec![0.0; items.len()];
+        Self::build_from_points(items, &mut nodes, VpSelection::Random);
+        VpTree::from_built(items.iter().collect(), nodes)
+    }
+
     /// Constructs a new [`VpTree`] from a slice of items using multiple threads, storing references to the original items.
     /// 
-    /// Querrying the tree is faster when storing owned items directly. Use [`Self::new`] or [`Self::new_parallel`] to store owned items.
+    /// Querying the tree is faster when storing owned items directly. Use [`Self::new`] or [`Self::new_parallel`] to store owned items.
     /// Building the tree using references might be faster on datatets with large datapoints. Benchmarking is recommended to determine the best approach for your use case. 
     pub fn new_index_parallel(items: &[T], threads: usize) -> VpTree<&T> 
     where
@@ -61,228 +211,1780 @@ impl<T: Distance<T>> VpTree<T> {
         VpTree::<&T>::new_parallel(items, threads)
     }
 
+    /// Constructs a new [`VpTree`] from a slice of items using multiple threads, storing references to the original items.
+    /// Additionally returns the position in `items` of every item of the tree, the tree item at index `i` is `items[map[i]]`.
+    /// 
+    /// The slice is neither copied nor reordered, indices returned by the tree can be translated to positions in `items` through the map.
+    pub fn new_index_with_map(items: &[T], threads: usize) -> (VpTree<&T>, Vec<usize>)
+    where
+        T: Sync,
+    {
+        let indexed = items.iter()
+            .enumerate()
+            .map(|(index, item)| Indexed { item, index })
+            .collect::<Vec<_>>();
+
+        let tree = VpTree::new_parallel(indexed, threads);
+        let map = tree.items.iter().map(|indexed| indexed.index).collect();
+        (tree.map_items(|indexed| indexed.item), map)
+    }
+
     /// Performs a query on the VpTree using the specified target and query parameters.
     /// Returns a vector of references to the items that match the query criteria.
-    pub fn querry<U, Q>(&self, target: &U, querry: Q) -> Vec<&T> 
+    pub fn query<U, Q>(&self, target: &U, query: Q) -> Vec<&T> 
     where
         U: Distance<T>,
-        Q: Borrow<Querry>,
+        Q: Borrow<Query>,
     {
-        let querry = querry.borrow();
-        let mut heap = BinaryHeap::new();
-        let mut tau = querry.max_distance;
+        self.search_items(target, query.borrow())
+            .into_iter()
+            .map(|item| &self.items[item.index])
+            .collect()
+    }
 
-        self.search_rec(Self::ROOT, self.items.len(), target, querry.max_items, &mut heap, &mut tau, querry.exclusive);
+    /// Performs a query on the VpTree and returns each matching item together with its distance to the target, see [`Self::query`].
+    /// The distances are the ones computed during the search, in sorted order if the query is sorted.
+    pub fn query_with_distances<U, Q>(&self, target: &U, query: Q) -> Vec<(&T, f64)>
+    where
+        U: Distance<T>,
+        Q: Borrow<Query>,
+    {
+        self.search_items(target, query.borrow())
+            .into_iter()
+            .map(|item| (&self.items[item.index], item.distance))
+            .collect()
+    }
 
-        if querry.sorted {
-            heap.into_sorted_vec()
-                .into_iter()
-                .map(|item| &self.items[item.index])
-                .collect()
-        } else {
-            heap.into_iter()
+    /// Deprecated spelling of [`Self::query`].
+    #[deprecated(note = "renamed to `query`")]
+    pub fn querry<U, Q>(&self, target: &U, query: Q) -> Vec<&T>
+    where
+        U: Distance<T>,
+        Q: Borrow<Query>,
+    {
+        self.query(target, query)
+    }
+
+    /// Performs a query on the VpTree returning at most one item per key, the nearest item among all items sharing a key.
+    /// The `max_items` limit of the query applies to the number of distinct keys.
+    /// 
+    /// If fewer distinct keys than requested are among the nearest items, the search is repeated with twice as many candidates.
+    pub fn query_unique_by<U, Q, K, F>(&self, target: &U, query: Q, key: F) -> Vec<&T>
+    where
+        U: Distance<T>,
+        Q: Borrow<Query>,
+        K: Eq + Hash,
+        F: Fn(&T) -> K,
+    {
+        let query = query.borrow();
+        let mut candidates = Query { sorted: true, ..query.clone() };
+
+        loop {
+            let items = self.search_items(target, &candidates);
+            let mut keys = HashSet::new();
+            let unique = items.iter()
                 .map(|item| &self.items[item.index])
-                .collect()
+                .filter(|item| keys.insert(key(item)))
+                .take(query.max_items)
+                .collect::<Vec<_>>();
+
+            if unique.len() == query.max_items || items.len() < candidates.max_items {
+                return unique;
+            }
+            candidates.max_items = candidates.max_items.saturating_mul(2);
         }
     }
 
-    /// Searches for the single nearest neighbor to the target. Results may include the target itself if it is present in the tree.
-    /// To exclude the target itself from the results (distance zero), use [`Self::nearest_neighbor_exclusive`].
-    pub fn nearest_neighbor<U: Distance<T>>(&self, target: &U) -> Option<&T> {
-        let mut best_index = None;
-        let mut best_distance = f64::INFINITY;
-        self.search_nearest_rec(Self::ROOT, self.items.len(), target, &mut best_index, &mut best_distance, false);
-        best_index.map(|index| &self.items[index])
+    /// Performs a query on the VpTree and returns owned clones of the matching items. See [`Self::query`].
+    pub fn query_cloned<U, Q>(&self, target: &U, query: Q) -> Vec<T>
+    where
+        U: Distance<T>,
+        Q: Borrow<Query>,
+        T: Clone,
+    {
+        let query = query.borrow();
+        Self::heap_items(self.search(target, query), query.sorted)
+            .into_iter()
+            .map(|item| self.items[item.index].clone())
+            .collect()
     }
 
-    /// Searches for the single nearest neighbor to the target, excluding the target itself if it is present in the tree.
-    /// To include the target itself in the results, use [`Self::nearest_neighbor`].
-    pub fn nearest_neighbor_exclusive<U: Distance<T>>(&self, target: &U) -> Option<&T> {
-        let mut best_index = None;
-        let mut best_distance = f64::INFINITY;
-        self.search_nearest_rec(Self::ROOT, self.items.len(), target, &mut best_index, &mut best_distance, true);
-        best_index.map(|index| &self.items[index])
+    /// Performs a query on the VpTree that visits at most `node_budget` nodes of the tree.
+    /// Returns the matching items and `true` if the budget was exhausted before the search completed, in which case the results may be incomplete.
+    /// If the flag is `false`, the results are identical to [`Self::query`].
+    pub fn query_capped<U, Q>(&self, target: &U, query: Q, node_budget: usize) -> (Vec<&T>, bool)
+    where
+        U: Distance<T>,
+        Q: Borrow<Query>,
+    {
+        let query = query.borrow();
+        let mut collector = BudgetCollector::new(KnnCollector::new(query), node_budget);
+        self.search_with(target, &mut collector);
+        (self.collect_heap(collector.inner.heap, query.sorted), collector.exhausted)
     }
 
-    /// Returns a reference to all items stored in the VpTree. The items are stored in an arbitrary order.
-    pub fn items(&self) -> &[T] {
-        &self.items
+    /// Performs a query on the VpTree and returns the results of [`Self::query`] together with [`SearchStats`] about the search.
+    pub fn query_with_stats<U, Q>(&self, target: &U, query: Q) -> (Vec<&T>, SearchStats)
+    where
+        U: Distance<T>,
+        Q: Borrow<Query>,
+    {
+        let query = query.borrow();
+        let mut collector = StatsCollector::new(KnnCollector::new(query));
+        self.search_with(target, &mut collector);
+
+        let mut stats = collector.stats;
+        if !self.items.is_empty() {
+            stats.fraction_of_tree_visited = stats.nodes_visited as f64 / self.items.len() as f64;
+        }
+        (self.collect_heap(collector.inner.heap, query.sorted), stats)
     }
 
-    /// Consumes the [`VpTree`] and returns the items stored within it. The items are returned in an arbitrary order.
-    pub fn into_items(self) -> Vec<T> {
-        self.items
+    /// Runs the query for every candidate target and returns the index and [`SearchStats`] of the candidate that visited the most nodes.
+    /// Useful to estimate worst-case query latency for a dataset.
+    /// 
+    /// Panics if `candidates` is empty.
+    pub fn worst_case_probe<U, Q>(&self, candidates: &[U], query: Q) -> (usize, SearchStats)
+    where
+        U: Distance<T>,
+        Q: Borrow<Query>,
+    {
+        let query = query.borrow();
+        candidates.iter()
+            .map(|target| self.query_with_stats(target, query).1)
+            .enumerate()
+            .rev()
+            .max_by_key(|(_, stats)| stats.nodes_visited)
+            .expect("candidates must not be empty")
     }
 
-    fn build_from_points_par(items: &mut[T], nodes: &mut [f64], threads: usize)
-    where 
-        T: Send,
+    /// Performs a query on the VpTree and sorts the results by a custom key computed from each item and its distance to the target.
+    /// Candidates are gathered exactly like in [`Self::query`], the `sorted` flag of the query is ignored.
+    /// 
+    /// Useful to rank results by a combination of distance and item properties without recomputing distances.
+    pub fn query_sorted_by<U, Q, K, F>(&self, target: &U, query: Q, key: F) -> Vec<&T>
+    where
+        U: Distance<T>,
+        Q: Borrow<Query>,
+        K: Ord,
+        F: Fn(&T, f64) -> K,
     {
-        if threads <= 1 {
-            return Self::build_from_points(items, nodes);
-        }
-    
-        if items.len() <= 1 {
-            return;
-        }
+        let mut results = self.search(target, query.borrow())
+            .into_iter()
+            .map(|item| (&self.items[item.index], item.distance))
+            .collect::<Vec<_>>();
 
-        let (left_slice, right_slice, left_nodes, right_nodes) = Self::internal_build(items, nodes);
+        results.sort_by_cached_key(|&(item, distance)| key(item, distance));
 
-        std::thread::scope(|s| {
-            s.spawn(|| Self::build_from_points_par(left_slice, left_nodes, threads / 2 + threads % 2));
-            Self::build_from_points_par(right_slice, right_nodes, threads / 2);
-        });
+        results.into_iter()
+            .map(|(item, _)| item)
+            .collect()
     }
 
-    fn build_from_points(items: &mut[T], nodes: &mut [f64]) {
-        if items.len() <= 1 {
-            return;
+    /// Performs a query on the VpTree and returns the indices of the matching items and their distances to the target as two separate columns.
+    /// Both columns are sorted by distance (closest first), the `sorted` flag of the query is ignored. Indices refer to [`Self::items`].
+    pub fn query_columns<U, Q>(&self, target: &U, query: Q) -> (Vec<usize>, Vec<f64>)
+    where
+        U: Distance<T>,
+        Q: Borrow<Query>,
+    {
+        let query = Query { sorted: true, ..query.borrow().clone() };
+        self.search_items(target, &query)
+            .into_iter()
+            .map(|item| (item.index, item.distance))
+            .unzip()
+    }
+
+    /// Performs a query on the VpTree and calls `f` with each matching item and its distance to the target, without collecting the results.
+    /// Returning [`ControlFlow::Break`] from `f` stops the query early.
+    /// 
+    /// The order in which items are passed to `f` is unspecified and the `sorted` flag of the query is ignored.
+    /// Queries without a limit on the number of items are streamed while the tree is traversed.
+    /// Queries limited to the nearest items have to finish the traversal first, since later items may replace earlier ones.
+    pub fn query_scan<'a, U, Q, F>(&'a self, target: &U, query: Q, mut f: F)
+    where
+        U: Distance<T>,
+        Q: Borrow<Query>,
+        F: FnMut(&'a T, f64) -> ControlFlow<()>,
+    {
+        let query = query.borrow();
+        if query.max_items == usize::MAX && !query.collapse_duplicates {
+            let mut collector = ScanCollector::new(query, |index, distance| f(&self.items[index], distance));
+            self.search_with(target, &mut collector);
+        } else {
+            for item in self.search(target, query) {
+                if f(&self.items[item.index], item.distance).is_break() {
+                    return;
+                }
+            }
         }
+    }
 
-        let (left_slice, right_slice, left_nodes, right_nodes) = Self::internal_build(items, nodes);
+    /// Performs a query on the VpTree and calls `f` with each result of [`Self::query`] and its distance to the target, in sorted order if the query is sorted.
+    /// 
+    /// Unsorted queries without a limit on the number of items are streamed while the tree is traversed, without allocating.
+    /// See [`Self::query_scan`] to stop early.
+    pub fn query_each<'a, U, F>(&'a self, target: &U, query: &Query, mut f: F)
+    where
+        U: Distance<T>,
+        F: FnMut(&'a T, f64),
+    {
+        if query.max_items == usize::MAX && !query.sorted && !query.collapse_duplicates {
+            let mut collector = ScanCollector::new(query, |index, distance| {
+                f(&self.items[index], distance);
+                ControlFlow::Continue(())
+            });
+            self.search_with(target, &mut collector);
+        } else {
+            for item in self.search_items(target, query) {
+                f(&self.items[item.index], item.distance);
+            }
+        }
+    }
 
-        Self::build_from_points(left_slice, left_nodes);
-        Self::build_from_points(right_slice, right_nodes);
+    /// Folds over the `k` nearest neighbors of the target, in order of increasing distance, without collecting them into a result vector.
+    /// `f` is called with the accumulator, the item and its distance to the target.
+    pub fn fold_k_nearest<'a, U, A, F>(&'a self, target: &U, k: usize, init: A, mut f: F) -> A
+    where
+        U: Distance<T>,
+        F: FnMut(A, &'a T, f64) -> A,
+    {
+        self.search(target, &Query::k_nearest_neighbors(k))
+            .into_sorted_vec()
+            .into_iter()
+            .fold(init, |acc, item| f(acc, &self.items[item.index], item.distance))
     }
 
-    #[inline(always)]
-    fn internal_build<'a>(items: &'a mut [T], nodes: &'a mut [f64]) -> (&'a mut [T], &'a mut [T], &'a mut [f64], &'a mut [f64]) {
-        let i = fastrand::usize(..items.len());
-        items.swap(0, i);
-        let (random_element, slice) = items.split_first_mut().unwrap();
-            
-        let median = slice.len() / 2;
+    /// Returns the distance from the target to its `k`-th nearest neighbor, or `None` if the tree holds fewer than `k` items.
+    /// Useful for k-distance plots, since no references to the neighbors are collected.
+    /// 
+    /// Panics if `k` is zero.
+    pub fn kth_distance<U: Distance<T>>(&self, target: &U, k: usize) -> Option<f64> {
+        let heap = self.search(target, &Query::k_nearest_neighbors(k));
+        if heap.len() < k {
+            return None;
+        }
+        heap.peek().map(|item| item.distance)
+    }
 
-        let (_, median_item, _) = slice.select_nth_unstable_by(median, |a, b| {
-            let dist_a = random_element.distance_heuristic(a);
-            let dist_b = random_element.distance_heuristic(b);
-            dist_a.partial_cmp(&dist_b).unwrap_or(std::cmp::Ordering::Less)
-        });
+    /// Returns `n` items sampled uniformly from all items within `radius` of the target, or all of them if there are fewer than `n`.
+    /// The sample is drawn by reservoir sampling during the traversal, so memory use does not depend on the number of matching items.
+    /// 
+    /// The same seed returns the same sample for the same tree.
+    pub fn sample_within_radius<U: Distance<T>>(&self, target: &U, radius: f64, n: usize, rng_seed: u64) -> Vec<&T> {
+        if n == 0 {
+            return Vec::new();
+        }
 
-        nodes[0] = random_element.distance(median_item);
+        let mut rng = fastrand::Rng::with_seed(rng_seed);
+        let mut sample = Vec::with_capacity(n);
+        let mut seen = 0;
 
-        let (left_slice, right_slice) = slice.split_at_mut(median);
-        let (left_nodes, right_nodes) = nodes[1..].split_at_mut(median);
+        self.query_scan(target, Query::neighbors_within_radius(radius), |item, _| {
+            seen += 1;
+            if sample.len() < n {
+                sample.push(item);
+            } else {
+                let i = rng.usize(..seen);
+                if i < n {
+                    sample[i] = item;
+                }
+            }
+            ControlFlow::Continue(())
+        });
+        sample
+    }
 
-        (left_slice, right_slice, left_nodes, right_nodes)
+    /// Returns all items within `radius` of the target (inclusive) together with their distance, sorted by distance (closest first).
+    /// The distances are the ones computed during the search, no distance is computed twice.
+    pub fn within_radius_sorted_with_distances<U: Distance<T>>(&self, target: &U, radius: f64) -> Vec<(&T, f64)> {
+        self.search_items(target, &Query::neighbors_within_radius(radius).sorted())
+            .into_iter()
+            .map(|item| (&self.items[item.index], item.distance))
+            .collect()
     }
 
-    fn search_rec<U: Distance<T>>(
-        &self,
-        node_index: usize,
-        len: usize,
-        target: &U,
-        k: usize,
-        heap: &mut BinaryHeap<HeapItem>,
-        tau: &mut f64,
-        exclusive: bool
-    ) {
-        if len == 0 {
-            return;
-        }
+    /// Downsamples the items to one representative per occupied grid cell, the item nearest to the center of its cell.
+    /// 
+    /// `coord` extracts the coordinates of an item, cells are axis aligned cubes with edge length `cell_size` starting at the origin.
+    /// Distances to the cell centers are Euclidean distances of the extracted coordinates. The representatives are returned in arbitrary order.
+    /// 
+    /// Panics if `cell_size` is not positive.
+    pub fn downsample_grid<const D: usize, F: Fn(&T) -> [f64; D]>(&self, cell_size: f64, coord: F) -> Vec<&T> {
+        assert!(cell_size > 0.0, "cell_size must be positive");
 
-        let threashold = &self.nodes[node_index];
-        let dist = target.distance(&self.items[node_index]);
+        let mut cells: HashMap<[i64; D], (usize, f64)> = HashMap::new();
+        for (index, item) in self.items.iter().enumerate() {
+            let coords = coord(item);
+            let cell = coords.map(|c| (c / cell_size).floor() as i64);
+            let distance = coords.iter()
+                .zip(cell)
+                .map(|(c, cell)| {
+                    let diff = c - (cell as f64 + 0.5) * cell_size;
+                    diff * diff
+                })
+                .sum::<f64>();
 
-        if dist <= *tau && (!exclusive || dist > 0.0) {
-            if heap.len() == k {
-                heap.pop();
-            }
-            heap.push(HeapItem { index: node_index, distance: dist });
-            if heap.len() == k && let Some(peek) = heap.peek() {
-                *tau = peek.distance;
+            let best = cells.entry(cell).or_insert((index, distance));
+            if distance < best.1 {
+                *best = (index, distance);
             }
         }
 
-        let left = node_index + 1;
-        let right = node_index + 1 + (len - 1) / 2;
-        let len_left = (len - 1) / 2;
-        let right_len = len - 1 - len_left;
+        cells.into_values()
+            .map(|(index, _)| &self.items[index])
+            .collect()
+    }
 
-        if dist <= *threashold {
-            self.search_rec(left, len_left, target, k, heap, tau, exclusive);
-            if dist + *tau >= *threashold {
-                self.search_rec(right, right_len, target, k, heap, tau, exclusive);
-            }
-        } else {
-            self.search_rec(right, right_len, target, k, heap, tau, exclusive);
-            if dist - *tau <= *threashold {
-                self.search_rec(left, len_left, target, k, heap, tau, exclusive);
+    /// Returns all items within the largest band edge of the target, grouped into distance bands in a single traversal.
+    /// Band `i` contains the items with `band_edges[i - 1] < distance <= band_edges[i]`, the first band starts at distance zero.
+    /// Items exactly on an edge belong to the lower band. The items within a band are in arbitrary order.
+    /// 
+    /// Panics if `band_edges` is not sorted in ascending order.
+    pub fn within_radius_banded<U: Distance<T>>(&self, target: &U, band_edges: &[f64]) -> Vec<Vec<&T>> {
+        assert!(band_edges.is_sorted(), "band_edges must be sorted in ascending order");
+
+        let mut bands = vec![Vec::new(); band_edges.len()];
+        let Some(&radius) = band_edges.last() else {
+            return bands;
+        };
+
+        self.query_scan(target, Query::neighbors_within_radius(radius), |item, distance| {
+            bands[band_edges.partition_point(|&edge| edge < distance)].push(item);
+            ControlFlow::Continue(())
+        });
+        bands
+    }
+
+    /// Returns the items within each of the radii of the target (inclusive) in a single traversal up to the largest radius.
+    /// Entry `i` contains the same items as a radius search with `radii[i]`, so the results of nested radii are nested as well.
+    /// See [`Self::within_radius_banded`] for disjoint bands. The items within an entry are in arbitrary order.
+    pub fn within_radii<U: Distance<T>>(&self, target: &U, radii: &[f64]) -> Vec<Vec<&T>> {
+        let mut results = vec![Vec::new(); radii.len()];
+        let Some(radius) = radii.iter().copied().reduce(f64::max) else {
+            return results;
+        };
+
+        self.query_scan(target, Query::neighbors_within_radius(radius), |item, distance| {
+            for (result, &radius) in results.iter_mut().zip(radii) {
+                if distance <= radius {
+                    result.push(item);
+                }
             }
+            ControlFlow::Continue(())
+        });
+        results
+    }
+
+    /// Returns representatives of the items across distance scales: the nearest item, then the nearest item at least `min_gaps[0]` farther away, and so on.
+    /// Result `i + 1` is the nearest item with a distance of at least the distance of result `i` plus `min_gaps[i]`.
+    /// 
+    /// Returns fewer than `min_gaps.len() + 1` items if no item lies beyond a gap. Runs one search per returned item.
+    /// 
+    /// Panics if a gap is not positive.
+    pub fn representatives_by_distance<U: Distance<T>>(&self, target: &U, min_gaps: &[f64]) -> Vec<&T> {
+        assert!(min_gaps.iter().all(|&gap| gap > 0.0), "min_gaps must be positive");
+
+        let mut representatives = Vec::with_capacity(min_gaps.len() + 1);
+        let mut min_distance = 0.0;
+        for gap in std::iter::once(0.0).chain(min_gaps.iter().copied()) {
+            let mut collector = NearestBeyondCollector::new(min_distance + gap);
+            self.search_with(target, &mut collector);
+            let Some(index) = collector.best_index else {
+                break;
+            };
+            representatives.push(&self.items[index]);
+            min_distance = collector.best_distance;
         }
+        representatives
     }
 
+    /// Performs a query on the VpTree and returns each matching item together with its path from the root of the tree.
+    /// The path lists the branch taken at every node on the way to the item, `false` for the left (inner) and `true` for the right (outer) subtree.
+    /// 
+    /// The root and inserted items that are not yet part of the tree structure have an empty path. Intended for debugging the tree structure.
+    pub fn query_with_paths<U, Q>(&self, target: &U, query: Q) -> Vec<(&T, Vec<bool>)>
+    where
+        U: Distance<T>,
+        Q: Borrow<Query>,
+    {
+        let query = query.borrow();
+        Self::heap_items(self.search(target, query), query.sorted)
+            .into_iter()
+            .map(|item| (&self.items[item.index], self.path_to(item.index)))
+            .collect()
+    }
 
+    /// Searches for the single nearest neighbor to the target. Results may include the target itself if it is present in the tree.
+    /// To exclude the target itself from the results (distance zero), use [`Self::nearest_neighbor_exclusive`].
+    pub fn nearest_neighbor<U: Distance<T>>(&self, target: &U) -> Option<&T> {
+        self.search_nearest(target, false).map(|index| &self.items[index])
+    }
 
-    fn search_nearest_rec<U: Distance<T>>(
-        &self,
-        node_index: usize,
-        len: usize,
-        target: &U,
-        best_index: &mut Option<usize>,
-        best_distance: &mut f64,
-        exclusive: bool
-    ) {
-        if len == 0 {
-            return;
-        }
+    /// Searches for the single nearest neighbor to the target, excluding the target itself if it is present in the tree.
+    /// To include the target itself in the results, use [`Self::nearest_neighbor`].
+    pub fn nearest_neighbor_exclusive<U: Distance<T>>(&self, target: &U) -> Option<&T> {
+        self.search_nearest(target, true).map(|index| &self.items[index])
+    }
 
-        let threashold = &self.nodes[node_index];
-        let dist = target.distance(&self.items[node_index]);
+    /// Searches for the single nearest neighbor to the target and returns its index, see [`Self::item`]. Results may include the target itself if it is present in the tree.
+    pub fn nearest_neighbor_index<U: Distance<T>>(&self, target: &U) -> Option<usize> {
+        self.search_nearest(target, false)
+    }
 
-        if dist < *best_distance && (!exclusive || dist > 0.0) {
-            *best_distance = dist;
-            *best_index = Some(node_index);
-        }
+    /// Searches for the single nearest neighbor to the target and returns its index, excluding the target itself if it is present in the tree.
+    /// See [`Self::nearest_neighbor_exclusive`].
+    pub fn nearest_neighbor_index_exclusive<U: Distance<T>>(&self, target: &U) -> Option<usize> {
+        self.search_nearest(target, true)
+    }
 
-        let left = node_index + 1;
-        let right = node_index + 1 + (len - 1) / 2;
-        let len_left = (len - 1) / 2;
-        let right_len = len - 1 - len_left;
+    /// Searches for the nearest neighbor of every target using multiple threads and returns the item indices as [`u32`], `None` if the tree is empty.
+    /// The indices refer to [`Self::items`], in the same order as the targets. See [`Self::nearest_neighbor_index`].
+    /// 
+    /// Panics if the tree holds more than `2^32` items, whose indices do not fit into a [`u32`].
+    pub fn nearest_neighbor_batch_indices<U>(&self, targets: &[U], threads: usize) -> Vec<Option<u32>>
+    where
+        T: Sync,
+        U: Distance<T> + Sync,
+    {
+        assert!(u32::try_from(self.items.len().saturating_sub(1)).is_ok(), "item indices must fit into a u32");
 
-        if dist <= *threashold {
-            self.search_nearest_rec(left, len_left, target, best_index, best_distance, exclusive);
-            if dist + *best_distance >= *threashold {
-                self.search_nearest_rec(right, right_len, target, best_index, best_distance, exclusive);
-            }
-        } else {
-            self.search_nearest_rec(right, right_len, target, best_index, best_distance, exclusive);
-            if dist - *best_distance <= *threashold {
-                self.search_nearest_rec(left, len_left, target, best_index, best_distance, exclusive);
+        let mut indices = vec![None; targets.len()];
+        let chunk_size = targets.len().div_ceil(threads.max(1)).max(1);
+
+        std::thread::scope(|s| {
+            for (chunk, targets) in indices.chunks_mut(chunk_size).zip(targets.chunks(chunk_size)) {
+                s.spawn(move || {
+                    for (index, target) in chunk.iter_mut().zip(targets) {
+                        *index = self.search_nearest(target, false).map(|index| index as u32);
+                    }
+                });
             }
-        }
+        });
+        indices
     }
-}
 
-impl<T: Distance<T>> FromIterator<T> for VpTree<T> {
-    /// Constructs a new [`VpTree`] from an iterator of items. The items are consumed and stored within the tree.
-    /// This constructor uses a single thread. For parallel construction, use [`Self::new_parallel`].
-    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
-        let items: Vec<T> = iter.into_iter().collect();
-        VpTree::new(items)
+    /// Searches for the nearest neighbor of every target along a path, returning the same results as calling [`Self::nearest_neighbor`] for each target.
+    /// 
+    /// Each search starts with the result of the previous target as candidate, which bounds the search radius from the start.
+    /// For closely spaced targets such as a smooth trajectory, this prunes most of the tree immediately.
+    pub fn nearest_along_path<U: Distance<T>>(&self, path: &[U]) -> Vec<Option<&T>> {
+        let mut previous = None;
+        path.iter()
+            .map(|target| {
+                previous = match previous {
+                    Some(hint_index) => self.nearest_neighbor_hinted(target, hint_index).map(|(index, _)| index),
+                    None => self.search_nearest(target, false),
+                };
+                previous.map(|index| &self.items[index])
+            })
+            .collect()
     }
-}
 
-pub struct HeapItem {
-    index: usize,
-    distance: f64,
-}
+    /// Searches for the nearest neighbor to the target starting with the item at `hint_index` as candidate, and returns its index and distance.
+    /// Results may include the target itself if it is present in the tree.
+    /// 
+    /// The distance to the hint bounds the search radius from the start, for example when passing the result for a slightly different previous target.
+    /// A distant hint only prunes less, the result is the same as [`Self::nearest_neighbor_index`] up to ties in distance.
+    /// 
+    /// Panics if the index is out of bounds.
+    pub fn nearest_neighbor_hinted<U: Distance<T>>(&self, target: &U, hint_index: usize) -> Option<(usize, f64)> {
+        let len = self.items.len();
+        assert!(hint_index < len, "index out of bounds: the len is {len} but the index is {hint_index}");
 
-impl PartialEq for HeapItem {
-    fn eq(&self, other: &Self) -> bool {
-        self.distance == other.distance
+        let hint_distance = target.distance(&self.items[hint_index]);
+        self.distance_calls.increment();
+        let mut collector = NearestCollector::new(false).seeded(hint_index, hint_distance);
+        if collector.best_distance > 0.0 {
+            self.search_with(target, &mut collector);
+        }
+        collector.best_index.map(|index| (index, collector.best_distance))
     }
-}
-impl Eq for HeapItem {}
 
-impl PartialOrd for HeapItem {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
+    /// Performs the query for every target, returning the same results as calling [`Self::query`] for each target, up to ties in distance.
+    /// 
+    /// If the previous target found all its requested items within distance `r`, the current target finds them within `r` plus the distance between both targets.
+    /// Each search starts pruning with this bound instead of the radius of the query. The bound is not used with [`Query::exclusive_boundary`].
+    /// Order the targets so that consecutive targets are close to each other, for example along a space-filling curve such as the Hilbert curve.
+    pub fn query_batch_locality_aware<U, Q>(&self, targets: &[U], query: Q) -> Vec<Vec<&T>>
+    where
+        U: Distance<T> + Distance<U>,
+        Q: Borrow<Query>,
+    {
+        let query = query.borrow();
+        let mut previous: Option<(&U, f64)> = None;
+        targets.iter()
+            .map(|target| {
+                let mut bounded = query.clone();
+                if let Some((previous_target, radius)) = previous {
+                    let bound = radius + Distance::<U>::distance(target, previous_target);
+                    bounded.max_distance = bounded.max_distance.min(bound);
+                }
+
+                let items = self.search_items(target, &bounded);
+                // The bound only holds if the previous target found every requested item.
+                // An exclusive boundary would drop items at exactly the bound, so it is not carried then.
+                previous = match items.iter().map(|item| item.distance).max_by(f64::total_cmp) {
+                    Some(radius) if items.len() == query.max_items && !query.exclusive && !query.exclusive_boundary && !query.collapse_duplicates => Some((target, radius)),
+                    _ => None,
+                };
+
+                items.into_iter()
+                    .map(|item| &self.items[item.index])
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Returns the indices of all items sorted by ascending distance to the target, the argsort of all items by distance.
+    /// The indices refer to [`Self::items`]. Takes O(n log n) time.
+    pub fn distance_rank_order<U: Distance<T>>(&self, target: &U) -> Vec<usize> {
+        self.search_items(target, &Query::default().sorted())
+            .into_iter()
+            .map(|item| item.index)
+            .collect()
+    }
+
+    /// Searches for the nearest other item to the stored item at `index` and returns its index and distance.
+    /// Only the item itself is excluded, duplicates of it at distance zero are valid results.
+    /// 
+    /// Panics if the index is out of bounds.
+    pub fn nearest_neighbor_of_item(&self, index: usize) -> Option<(usize, f64)> {
+        let len = self.items.len();
+        assert!(index < len, "index out of bounds: the len is {len} but the index is {index}");
+
+        let mut collector = NearestCollector::new(false).excluding(index);
+        self.search_with(&self.items[index], &mut collector);
+        collector.best_index.map(|best| (best, collector.best_distance))
+    }
+
+    /// Returns the indices of the two closest items and their distance, or `None` with fewer than two items.
+    /// The smaller index comes first, indices refer to the order of [`Self::items`].
+    /// 
+    /// Searches the nearest other item of every item, see [`Self::nearest_neighbor_of_item`], stopping early at a pair of duplicates.
+    pub fn closest_pair(&self) -> Option<(usize, usize, f64)> {
+        let mut closest: Option<(usize, usize, f64)> = None;
+        for index in 0..self.items.len() {
+            let Some((nearest, distance)) = self.nearest_neighbor_of_item(index) else {
+                continue;
+            };
+            if closest.is_none_or(|(_, _, best)| distance < best) {
+                closest = Some((index.min(nearest), index.max(nearest), distance));
+                if distance == 0.0 {
+                    break;
+                }
+            }
+        }
+        closest
+    }
+
+    /// Builds the k-nearest neighbor graph of all items. Entry `i` lists the indices of the `k` nearest other items of the stored item at index `i`, closest first.
+    /// Indices refer to the order of [`Self::items`]. Only the item itself is excluded, duplicates of it are valid neighbors.
+    pub fn knn_graph(&self, k: usize) -> Vec<Vec<usize>> {
+        (0..self.items.len())
+            .map(|index| self.knn_of_item(index, k).into_iter().map(|item| item.index).collect())
+            .collect()
+    }
+
+    /// Builds the k-nearest neighbor graph of all items using multiple threads. See [`Self::knn_graph`].
+    pub fn knn_graph_parallel(&self, k: usize, threads: usize) -> Vec<Vec<usize>>
+    where
+        T: Sync,
+    {
+        let mut graph = vec![Vec::new(); self.items.len()];
+        let chunk_size = self.items.len().div_ceil(threads.max(1)).max(1);
+
+        std::thread::scope(|s| {
+            for (chunk_index, chunk) in graph.chunks_mut(chunk_size).enumerate() {
+                s.spawn(move || {
+                    for (offset, neighbors) in chunk.iter_mut().enumerate() {
+                        *neighbors = self.knn_of_item(chunk_index * chunk_size + offset, k)
+                            .into_iter()
+                            .map(|item| item.index)
+                            .collect();
+                    }
+                });
+            }
+        });
+        graph
+    }
+
+    /// Groups the items into connected components, two items within `radius` of each other (inclusive) being connected.
+    /// This is single-linkage clustering at a fixed radius. Each component lists the indices of its items in ascending order, see [`Self::item`].
+    /// Components are ordered by their smallest index.
+    /// 
+    /// Performs one radius search per item, which may be expensive for large radii.
+    pub fn connected_components(&self, radius: f64) -> Vec<Vec<usize>> {
+        let query = Query::neighbors_within_radius(radius);
+        let mut parent: Vec<usize> = (0..self.items.len()).collect();
+        for index in 0..self.items.len() {
+            let mut collector = ScanCollector::new(&query, |neighbor, _| {
+                let (a, b) = (Self::find_root(&mut parent, index), Self::find_root(&mut parent, neighbor));
+                parent[a.max(b)] = a.min(b);
+                ControlFlow::Continue(())
+            });
+            self.search_with(&self.items[index], &mut collector);
+        }
+
+        let mut components: Vec<Vec<usize>> = Vec::new();
+        let mut component_of_root = vec![usize::MAX; self.items.len()];
+        for index in 0..self.items.len() {
+            let root = Self::find_root(&mut parent, index);
+            if component_of_root[root] == usize::MAX {
+                component_of_root[root] = components.len();
+                components.push(Vec::new());
+            }
+            components[component_of_root[root]].push(index);
+        }
+        components
+    }
+
+    /// Computes the neighborhood of the `k` nearest other items of every item, as needed by the Local Outlier Factor.
+    /// Entry `i` belongs to the stored item at index `i`. Like [`Self::knn_graph`], only the item itself is excluded.
+    /// 
+    /// The reachability distance of item `a` from its neighbor `b` is `max(neighborhoods[b].kth_distance, distance(a, b))`.
+    /// Uses one thread per available core, see [`std::thread::available_parallelism`].
+    pub fn lof_neighborhoods(&self, k: usize) -> Vec<Neighborhood>
+    where
+        T: Sync,
+    {
+        let mut neighborhoods = vec![Neighborhood::default(); self.items.len()];
+        let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+        let chunk_size = self.items.len().div_ceil(threads).max(1);
+
+        std::thread::scope(|s| {
+            for (chunk_index, chunk) in neighborhoods.chunks_mut(chunk_size).enumerate() {
+                s.spawn(move || {
+                    for (offset, neighborhood) in chunk.iter_mut().enumerate() {
+                        let nearest = self.knn_of_item(chunk_index * chunk_size + offset, k);
+                        *neighborhood = Neighborhood {
+                            indices: nearest.iter().map(|item| item.index).collect(),
+                            distances: nearest.iter().map(|item| item.distance).collect(),
+                            kth_distance: nearest.last().map_or(f64::INFINITY, |item| item.distance),
+                        };
+                    }
+                });
+            }
+        });
+        neighborhoods
+    }
+
+    /// Estimates the intrinsic dimensionality of the items using the two-nearest-neighbor (TwoNN) estimator.
+    /// 
+    /// For up to `samples` randomly chosen items, the ratio `mu = r2 / r1` of the distances to their second and first nearest other item is computed.
+    /// For locally uniform data of dimension `d`, `mu` follows a Pareto distribution with shape `d`, estimated by maximum likelihood as `n / sum(ln(mu))`.
+    /// Samples with duplicates at distance zero are skipped. Returns `NaN` if no sample could be used, for example with fewer than three items.
+    /// 
+    /// A high intrinsic dimensionality indicates that searches can prune only few subtrees.
+    pub fn estimate_intrinsic_dim(&self, samples: usize) -> f64 {
+        let len = self.items.len();
+        let indices: Vec<usize> = if samples >= len {
+            (0..len).collect()
+        } else {
+            (0..samples).map(|_| fastrand::usize(..len)).collect()
+        };
+
+        let (count, sum) = indices.into_iter()
+            .filter_map(|index| match self.knn_of_item(index, 2).as_slice() {
+                [first, second] if first.distance > 0.0 => Some((second.distance / first.distance).ln()),
+                _ => None,
+            })
+            .fold((0usize, 0.0), |(count, sum), log_mu| (count + 1, sum + log_mu));
+
+        if count == 0 {
+            return f64::NAN;
+        }
+        count as f64 / sum
+    }
+
+    /// Returns the item closest to the reference according to a comparator, for closeness measures that can only rank items but have no numeric distance.
+    /// `cmp(reference, a, b)` returns [`std::cmp::Ordering::Less`] if `a` is closer to the reference than `b`. For ties, the first item in [`Self::items`] order is returned.
+    /// 
+    /// Without a distance the tree can not prune, this performs a linear scan over all items in O(n).
+    /// Prefer [`Self::nearest_neighbor`] whenever a metric [`Distance`] is available.
+    pub fn nearest_by_cmp<U, F>(&self, reference: &U, cmp: F) -> Option<&T>
+    where
+        F: Fn(&U, &T, &T) -> std::cmp::Ordering,
+    {
+        self.items.iter().reduce(|best, item| {
+            if cmp(reference, item, best).is_lt() {
+                item
+            } else {
+                best
+            }
+        })
+    }
+
+    /// Searches for all items tied for the smallest distance to the target. Results may include the target itself if it is present in the tree.
+    /// Returns an empty vector if the tree is empty. The order of the returned items is unspecified.
+    pub fn all_nearest<U: Distance<T>>(&self, target: &U) -> Vec<&T> {
+        let mut collector = AllNearestCollector::new();
+        self.search_with(target, &mut collector);
+        collector.indices.into_iter()
+            .map(|index| &self.items[index])
+            .collect()
+    }
+
+    /// Searches for the single nearest neighbor to the target, resolving ties with `prefer`. Results may include the target itself if it is present in the tree.
+    /// `prefer(a, b)` receives the indices of two equidistant items, see [`Self::item`], and returns the index to keep.
+    ///
+    /// Items added with [`Self::insert`] are stored after all other items until the next rebuild, `prefer = usize::max` picks the most recently inserted among them.
+    pub fn nearest_neighbor_tie_break<U, F>(&self, target: &U, prefer: F) -> Option<&T>
+    where
+        U: Distance<T>,
+        F: Fn(usize, usize) -> usize,
+    {
+        let mut collector = AllNearestCollector::new();
+        self.search_with(target, &mut collector);
+        collector.indices.into_iter()
+            .reduce(prefer)
+            .map(|index| &self.items[index])
+    }
+
+    /// Returns `true` if any item lies within `radius` of the target (inclusive).
+    /// The search stops at the first item found, which makes it cheaper than counting or collecting all items within the radius.
+    pub fn exists_within<U: Distance<T>>(&self, target: &U, radius: f64) -> bool {
+        let mut collector = ExistsCollector::new(radius);
+        self.search_with(target, &mut collector);
+        collector.found
+    }
+
+    /// Inserts an item into the VpTree.
+    /// 
+    /// The item is not part of the tree structure until the next rebuild, searches compare it linearly against the target.
+    /// The tree rebuilds itself according to its [`RebuildPolicy`], see [`Self::set_rebuild_policy`].
+    pub fn insert(&mut self, item: T) {
+        self.items.push(item);
+        self.mutations += 1;
+        self.apply_rebuild_policy();
+    }
+
+    /// Inserts the item only if no item lies within `min_distance` of it (inclusive), otherwise returns the item in `Err`.
+    /// 
+    /// Repeated calls maintain a point set with a minimum spacing between all items. See [`Self::exists_within`] and [`Self::insert`].
+    pub fn insert_if_spaced(&mut self, item: T, min_distance: f64) -> Result<(), T> {
+        if self.exists_within(&item, min_distance) {
+            return Err(item);
+        }
+        self.insert(item);
+        Ok(())
+    }
+
+    /// Inserts a batch of items into the VpTree, checking the [`RebuildPolicy`] once for the whole batch.
+    /// 
+    /// The items are not part of the tree structure until the next rebuild. Instead, the first search after a burst of appends indexes all items
+    /// inserted since the last rebuild once, which makes that search O(m log m) for m such items. Later searches prune them like the items of the tree.
+    /// Searches take `&self`, so the index is kept next to the tree structure. Call [`Self::rebuild`] or use a [`RebuildPolicy`] to merge the items into it.
+    pub fn append(&mut self, items: Vec<T>) {
+        self.mutations += items.len();
+        self.items.extend(items);
+        self.pending = PendingIndex::new(self.items.len() - self.indexed_len());
+        self.apply_rebuild_policy();
+    }
+
+    /// Removes every item with fewer than `min_neighbors` other items within `radius` (inclusive) and rebuilds the tree.
+    /// Neighbors are counted among all items before any removal, as for the noise points of DBSCAN.
+    /// 
+    /// Takes one search per item plus a rebuild.
+    pub fn prune_sparse(&mut self, radius: f64, min_neighbors: usize) {
+        if min_neighbors == 0 {
+            return;
+        }
+
+        let query = Query::k_nearest_neighbors_within_radius(min_neighbors, radius);
+        let keep = (0..self.items.len())
+            .map(|index| {
+                let mut collector = KnnCollector::new(&query).excluding(index);
+                self.search_with(&self.items[index], &mut collector);
+                collector.heap.len() == min_neighbors
+            })
+            .collect::<Vec<_>>();
+
+        let mut keep = keep.into_iter();
+        self.items.retain(|_| keep.next().unwrap_or(true));
+        self.rebuild();
+    }
+
+    /// Sets the [`RebuildPolicy`] used to decide when the VpTree rebuilds itself after mutations.
+    /// The policy is checked immediately, and after every following mutation.
+    pub fn set_rebuild_policy(&mut self, policy: RebuildPolicy) {
+        self.rebuild_policy = policy;
+        self.apply_rebuild_policy();
+    }
+
+    /// Rebuilds the tree structure over all stored items, including inserted items that are not yet part of the structure.
+    /// Indices of items change during a rebuild.
+    pub fn rebuild(&mut self) {
+        Self::debug_assert_heuristic(&self.items);
+        #[cfg(feature = "compact-thresholds")]
+        {
+            self.compact_nodes = None;
+        }
+        self.nodes.resize(self.items.len(), 0.0);
+        Self::build_from_points(&mut self.items, &mut self.nodes, self.selection);
+        self.mutations = 0;
+        self.modified.clear();
+        self.pending = PendingIndex::default();
+    }
+
+    /// Rebuilds the tree structure over all stored items like [`Self::rebuild`], selecting vantage points with the given [`VpSelection`].
+    /// The selection is kept for all following rebuilds. Indices of items change during a rebuild.
+    pub fn rebuild_with_selection(&mut self, selection: VpSelection) {
+        self.selection = selection;
+        self.rebuild();
+    }
+
+    /// Stores the thresholds of the tree structure as [`f32`] instead of [`f64`], halving their memory. Requires the `compact-thresholds` feature.
+    /// 
+    /// Every threshold is rounded up to the next [`f32`], and the [`f32`] below it bounds the items of the right subtree.
+    /// Searches prune only beyond these bounds, so results stay exact, but nodes close to a threshold may be explored unnecessarily.
+    /// The effect is small unless distances are large compared to their differences, where [`f32`] is coarse.
+    /// 
+    /// The next [`Self::rebuild`] restores [`f64`] thresholds. [`Self::rebuild_subtree`] and [`Self::into_parts`] first recompute [`f64`] thresholds
+    /// from the distances of every vantage point to its left subtree.
+    #[cfg(feature = "compact-thresholds")]
+    pub fn compact_thresholds(&mut self) {
+        if self.compact_nodes.is_some() {
+            return;
+        }
+        let compact = self.nodes.iter()
+            .map(|&threshold| {
+                let rounded = threshold as f32;
+                if (rounded as f64) < threshold { rounded.next_up() } else { rounded }
+            })
+            .collect();
+        self.compact_nodes = Some(compact);
+        self.nodes = Vec::new();
+    }
+
+    /// Replaces all items while keeping the tree structure, avoiding a rebuild when only non-geometric data of the items changed.
+    /// 
+    /// `new_items` has to be in the order returned by [`Self::items`], and every new item must have the same distances to all other items as the item it replaces.
+    /// Otherwise queries return incorrect results.
+    /// 
+    /// Panics if the number of items differs.
+    pub fn rebuild_items_only(&mut self, new_items: Vec<T>) {
+        assert_eq!(new_items.len(), self.items.len(), "new_items must have the same length as the items of the tree");
+        self.items = new_items;
+    }
+
+    /// Rebuilds the subtree rooted at `node`, leaving the rest of the tree untouched.
+    /// 
+    /// Useful after modifying items through [`Self::items_mut_tracked`] when the modified items stay within the subtree,
+    /// i.e. every item still lies on the same side of the thresholds of all ancestors of `node`. Otherwise use [`Self::rebuild`].
+    /// 
+    /// Panics if `node` does not belong to this tree.
+    pub fn rebuild_subtree(&mut self, node: NodeId) {
+        let range = node.index..node.index + node.len;
+        assert!(range.end <= self.indexed_len(), "node {node:?} does not belong to a tree with {} nodes", self.indexed_len());
+        self.expand_thresholds();
+
+        Self::debug_assert_heuristic(&self.items[range.clone()]);
+        Self::build_from_points(&mut self.items[range.clone()], &mut self.nodes[range.clone()], self.selection);
+
+        // Items moved within the subtree, tracked indices in the range no longer refer to the modified items.
+        let len = self.modified.len();
+        self.modified.retain(|index| !range.contains(index));
+        if self.modified.len() != len {
+            self.modified.extend(range);
+        }
+    }
+
+    /// Returns the root node of the tree, or `None` if the tree structure is empty.
+    pub fn root_node(&self) -> Option<NodeId> {
+        NodeId::new(Self::ROOT, self.indexed_len())
+    }
+
+    /// Returns the left (inner) child of `node`, containing the items within the threshold of the node.
+    pub fn left_child(&self, node: NodeId) -> Option<NodeId> {
+        NodeId::new(node.index + 1, (node.len - 1) / 2)
+    }
+
+    /// Returns the right (outer) child of `node`, containing the items outside the threshold of the node.
+    pub fn right_child(&self, node: NodeId) -> Option<NodeId> {
+        let len_left = (node.len - 1) / 2;
+        NodeId::new(node.index + 1 + len_left, node.len - 1 - len_left)
+    }
+
+    /// Returns the distance threshold separating the left and right subtree of `node`.
+    /// After [`Self::compact_thresholds`], this is the threshold rounded up to the next [`f32`].
+    pub fn node_threshold(&self, node: NodeId) -> f64 {
+        self.threshold_bounds(node.index).0
+    }
+
+    /// Returns an indented text dump of the tree structure for debugging small trees, with `fmt` labeling the items.
+    /// 
+    /// Every node is printed as its vantage point label followed by its threshold, its children are indented below prefixed with `L:` and `R:`.
+    /// Leaves have no threshold. Inserted items that are not yet part of the tree structure are listed at the end.
+    /// 
+    /// ## Example
+    /// ```rust
+    /// use vp_tree::{Distance, VpTree};
+    /// 
+    /// struct Point(f64);
+    /// 
+    /// impl Distance<Point> for Point {
+    ///     fn distance(&self, other: &Point) -> f64 {
+    ///         (self.0 - other.0).abs()
+    ///     }
+    /// }
+    /// 
+    /// let mut vp_tree = VpTree::from_parts(vec![Point(1.0), Point(0.0), Point(2.5)], vec![1.5, 0.0, 0.0]);
+    /// vp_tree.insert(Point(7.0));
+    /// 
+    /// let dump = vp_tree.debug_dump(|p| p.0.to_string());
+    /// assert_eq!(dump, "1 threshold=1.5\n  L: 0\n  R: 2.5\nunindexed: 7\n");
+    /// ```
+    pub fn debug_dump<F: Fn(&T) -> String>(&self, fmt: F) -> String {
+        let mut dump = String::new();
+        if let Some(root) = self.root_node() {
+            self.dump_node(root, 0, "", &fmt, &mut dump);
+        }
+        for item in &self.items[self.indexed_len()..] {
+            dump.push_str(&format!("unindexed: {}\n", fmt(item)));
+        }
+        dump
+    }
+
+    /// Returns the items of the subtree rooted at `node`, which are stored contiguously.
+    /// The first item is the vantage point of `node`, followed by the items of the left subtree and then the right subtree.
+    /// 
+    /// Panics if `node` does not belong to this tree.
+    pub fn subtree_items(&self, node: NodeId) -> &[T] {
+        &self.items[node.index..node.index + node.len]
+    }
+
+    /// Returns the number of distance calculations on the longest path a search may take through the tree.
+    /// Inserted items that are not yet part of the tree structure are compared by every search and add to the depth.
+    pub fn depth(&self) -> usize {
+        Self::balanced_depth(self.indexed_len()) + self.items.len() - self.indexed_len()
+    }
+
+    /// Returns the [`Self::depth`] of a freshly built tree with the same number of items.
+    pub fn expected_depth(&self) -> usize {
+        Self::balanced_depth(self.items.len())
+    }
+
+    /// Returns a summary of the tree structure, computed in a single traversal. See [`TreeStats`].
+    pub fn stats(&self) -> TreeStats {
+        let (leaf_count, leaf_depth_sum) = Self::leaf_depths(self.indexed_len(), 1);
+        let depth = self.depth();
+        let expected_depth = self.expected_depth();
+
+        TreeStats {
+            len: self.items.len(),
+            depth,
+            expected_depth,
+            imbalance_ratio: if expected_depth == 0 { 1.0 } else { depth as f64 / expected_depth as f64 },
+            leaf_count,
+            avg_leaf_depth: if leaf_count == 0 { 0.0 } else { leaf_depth_sum as f64 / leaf_count as f64 },
+            root_threshold: self.root_node().map(|root| self.node_threshold(root)),
+        }
+    }
+
+    /// Returns a reference to the item stored at `index`, or `None` if the index is out of bounds.
+    pub fn item(&self, index: usize) -> Option<&T> {
+        self.items.get(index)
+    }
+
+    /// Returns the [`Distance::distance`] between the stored items at index `i` and `j`.
+    /// 
+    /// Panics if either index is out of bounds.
+    pub fn distance_between(&self, i: usize, j: usize) -> f64 {
+        let (a, b) = self.item_pair(i, j);
+        a.distance(b)
+    }
+
+    /// Returns the [`Distance::distance_heuristic`] between the stored items at index `i` and `j`.
+    /// 
+    /// Panics if either index is out of bounds.
+    pub fn distance_heuristic_between(&self, i: usize, j: usize) -> f64 {
+        let (a, b) = self.item_pair(i, j);
+        a.distance_heuristic(b)
+    }
+
+    /// Returns mutable access to the stored items that records which items were accessed.
+    /// 
+    /// Changing the distance of an item to other items can invalidate the tree structure, resulting in incorrect search results.
+    /// Call [`Self::revalidate`] after modifying items to check whether the tree is still valid.
+    pub fn items_mut_tracked(&mut self) -> TrackedItemsMut<'_, T> {
+        // Appended items may change, they are indexed again by the next search
+        self.pending.tree = OnceLock::new();
+        TrackedItemsMut { items: &mut self.items, modified: &mut self.modified }
+    }
+
+    /// Checks whether the items accessed through [`Self::items_mut_tracked`] since the last check still satisfy the thresholds of the tree structure.
+    /// Returns `false` if any of them violates the structure, in which case the tree has to be rebuilt using [`Self::rebuild`] to return correct search results.
+    /// 
+    /// Only the path from the root to each modified item and the subtree below it are checked.
+    pub fn revalidate(&mut self) -> bool {
+        let mut modified = std::mem::take(&mut self.modified);
+        modified.sort_unstable();
+        modified.dedup();
+        modified.into_iter()
+            .filter(|&index| index < self.indexed_len())
+            .all(|index| self.validate_item(index))
+    }
+
+    /// Returns the number of distance evaluations performed by searches on this tree since construction or the last [`Self::reset_counters`].
+    /// Requires the `instrument` feature.
+    #[cfg(feature = "instrument")]
+    pub fn distance_call_count(&self) -> u64 {
+        self.distance_calls.get()
+    }
+
+    /// Resets the counter returned by [`Self::distance_call_count`]. Requires the `instrument` feature.
+    #[cfg(feature = "instrument")]
+    pub fn reset_counters(&self) {
+        self.distance_calls.reset();
+    }
+
+    /// Returns the allocated capacity of the item and threshold storage as `(items, thresholds)`.
+    /// Compared to the number of [`Self::items`], this shows when inserts cause reallocations.
+    pub fn capacity(&self) -> (usize, usize) {
+        #[cfg(feature = "compact-thresholds")]
+        if let Some(compact) = &self.compact_nodes {
+            return (self.items.capacity(), compact.len());
+        }
+        (self.items.capacity(), self.nodes.capacity())
+    }
+
+    /// Reads the item and threshold storage once to fault evicted memory pages back in, for example before a latency critical burst of queries after an idle period.
+    /// One byte of every page spanned by the items and by the thresholds is loaded. Data referenced by the items, such as heap allocations, is not touched.
+    /// 
+    /// This is advisory and has no effect on results. Whether the pages stay resident afterwards is up to the operating system.
+    pub fn prefetch(&self) {
+        #[cfg(feature = "compact-thresholds")]
+        if let Some(compact) = &self.compact_nodes {
+            Self::touch_pages(compact);
+        }
+        Self::touch_pages(&self.nodes);
+        Self::touch_pages(&self.items);
+    }
+
+    /// Consumes the [`VpTree`] and returns the items stored within it. The items are returned in an arbitrary order.
+    pub fn into_items(self) -> Vec<T> {
+        self.items
+    }
+
+    /// Consumes the [`VpTree`] and returns the items in tree order together with the threshold of every node.
+    /// 
+    /// The tree structure is implicit in the order of the items, so the thresholds are all that is needed to restore the tree with [`Self::from_parts`] without rebuilding it.
+    /// Items past the last threshold were inserted after the last build and are not yet part of the tree structure.
+    /// 
+    /// After [`Self::compact_thresholds`], valid [`f64`] thresholds are recomputed from the distances of every vantage point to its left subtree.
+    pub fn into_parts(mut self) -> (Vec<T>, Vec<f64>) {
+        self.expand_thresholds();
+        (self.items, self.nodes)
+    }
+
+    /// Restores a [`VpTree`] from the parts returned by [`Self::into_parts`] without rebuilding it.
+    /// 
+    /// The items must be in the returned order and distances between them must be unchanged, otherwise queries return incorrect results.
+    /// Panics if there are more thresholds than items.
+    pub fn from_parts(items: Vec<T>, thresholds: Vec<f64>) -> Self {
+        assert!(thresholds.len() <= items.len(), "more thresholds than items: {} thresholds for {} items", thresholds.len(), items.len());
+        Self::from_built(items, thresholds)
+    }
+
+    /// Returns references to all items sorted by their distance to the target (closest first), without consuming the tree.
+    /// See [`Self::into_sorted_by_distance`] for the consuming variant.
+    pub fn sorted_refs_by_distance<U: Distance<T>>(&self, target: &U) -> Vec<&T> {
+        self.query(target, Query::default().sorted())
+    }
+
+    /// Consumes the [`VpTree`] and returns all items sorted by their distance to the target (closest first).
+    pub fn into_sorted_by_distance<U: Distance<T>>(self, target: &U) -> Vec<T> {
+        let order = self.search_items(target, &Query::default().sorted());
+        let mut items = self.items.into_iter().map(Some).collect::<Vec<_>>();
+        order.into_iter()
+            .map(|item| items[item.index].take().unwrap())
+            .collect()
+    }
+
+    fn from_built(items: Vec<T>, nodes: Vec<f64>) -> Self {
+        VpTree { items, nodes, ..Self::default() }
+    }
+
+    fn balanced_depth(len: usize) -> usize {
+        (usize::BITS - len.leading_zeros()) as usize
+    }
+
+    /// Returns the number of leaves of a subtree with `len` nodes and the sum of their depths, the root of the subtree being at `depth`.
+    fn leaf_depths(len: usize, depth: usize) -> (usize, usize) {
+        match len {
+            0 => (0, 0),
+            1 => (1, depth),
+            _ => {
+                let len_left = (len - 1) / 2;
+                let (left_leaves, left_sum) = Self::leaf_depths(len_left, depth + 1);
+                let (right_leaves, right_sum) = Self::leaf_depths(len - 1 - len_left, depth + 1);
+                (left_leaves + right_leaves, left_sum + right_sum)
+            }
+        }
+    }
+
+    /// Returns the root of the union-find set containing `index`, halving the path on the way.
+    fn find_root(parent: &mut [usize], mut index: usize) -> usize {
+        while parent[index] != index {
+            parent[index] = parent[parent[index]];
+            index = parent[index];
+        }
+        index
+    }
+
+    /// Loads one byte of every memory page spanned by `elements` using volatile reads, which the compiler can not remove.
+    fn touch_pages<E>(elements: &[E]) {
+        let len = size_of_val(elements);
+        let bytes = elements.as_ptr().cast::<MaybeUninit<u8>>();
+        // The slice does not have to start at a page boundary, the last byte covers the page it ends in
+        for offset in (0..len).step_by(Self::PAGE_SIZE).chain(len.checked_sub(1)) {
+            // SAFETY: `offset` is less than the size of the slice in bytes, and `MaybeUninit<u8>` may hold any byte including padding.
+            std::hint::black_box(unsafe { bytes.add(offset).read_volatile() });
+        }
+    }
+
+    fn apply_rebuild_policy(&mut self) {
+        let rebuild = match self.rebuild_policy {
+            RebuildPolicy::Never => false,
+            RebuildPolicy::AfterMutations(mutations) => self.mutations >= mutations,
+            RebuildPolicy::WhenImbalanceExceeds(ratio) => self.depth() as f64 > self.expected_depth() as f64 * ratio,
+        };
+        if rebuild && self.mutations > 0 {
+            self.rebuild();
+        }
+    }
+
+    fn dump_node<F: Fn(&T) -> String>(&self, node: NodeId, depth: usize, prefix: &str, fmt: &F, dump: &mut String) {
+        let label = fmt(&self.items[node.index]);
+        let indent = "  ".repeat(depth);
+        if node.len == 1 {
+            dump.push_str(&format!("{indent}{prefix}{label}\n"));
+            return;
+        }
+        dump.push_str(&format!("{indent}{prefix}{label} threshold={}\n", self.node_threshold(node)));
+        if let Some(left) = self.left_child(node) {
+            self.dump_node(left, depth + 1, "L: ", fmt, dump);
+        }
+        if let Some(right) = self.right_child(node) {
+            self.dump_node(right, depth + 1, "R: ", fmt, dump);
+        }
+    }
+
+    fn knn_of_item(&self, index: usize, k: usize) -> Vec<HeapItem> {
+        let query = Query::k_nearest_neighbors(k);
+        let mut collector = KnnCollector::new(&query).excluding(index);
+        self.search_with(&self.items[index], &mut collector);
+        collector.heap.into_sorted_vec()
+    }
+
+    /// Replaces compacted thresholds by [`f64`] thresholds, the largest distance of every vantage point to its left subtree.
+    fn expand_thresholds(&mut self) {
+        #[cfg(feature = "compact-thresholds")]
+        if self.compact_nodes.is_some() {
+            self.nodes = (0..self.indexed_len()).map(|node_index| self.threshold_bounds(node_index).1).collect();
+            self.compact_nodes = None;
+            let mut stack = vec![(Self::ROOT, self.nodes.len())];
+            while let Some((node_index, len)) = stack.pop() {
+                if len <= 1 {
+                    continue;
+                }
+                let len_left = (len - 1) / 2;
+                let vantage = &self.items[node_index];
+                // Without a left subtree, the outer bound is a valid threshold
+                if let Some(max_left) = self.items[node_index + 1..node_index + 1 + len_left].iter().map(|item| vantage.distance(item)).reduce(f64::max) {
+                    self.nodes[node_index] = max_left;
+                }
+                stack.push((node_index + 1, len_left));
+                stack.push((node_index + 1 + len_left, len - 1 - len_left));
+            }
+        }
+    }
+
+    fn path_to(&self, index: usize) -> Vec<bool> {
+        let mut path = Vec::new();
+        if index >= self.indexed_len() {
+            return path;
+        }
+
+        let mut node_index = Self::ROOT;
+        let mut len = self.indexed_len();
+        while node_index != index {
+            let len_left = (len - 1) / 2;
+            if index <= node_index + len_left {
+                path.push(false);
+                node_index += 1;
+                len = len_left;
+            } else {
+                path.push(true);
+                node_index += 1 + len_left;
+                len -= 1 + len_left;
+            }
+        }
+        path
+    }
+
+    fn validate_item(&self, index: usize) -> bool {
+        let item = &self.items[index];
+        let mut node_index = Self::ROOT;
+        let mut len = self.indexed_len();
+
+        while node_index != index {
+            let len_left = (len - 1) / 2;
+            let dist = self.items[node_index].distance(item);
+            let (inner, outer) = self.threshold_bounds(node_index);
+            if index <= node_index + len_left {
+                if dist > inner {
+                    return false;
+                }
+                node_index += 1;
+                len = len_left;
+            } else {
+                if dist < outer {
+                    return false;
+                }
+                node_index += 1 + len_left;
+                len -= 1 + len_left;
+            }
+        }
+
+        self.validate_subtree(node_index, len)
+    }
+
+    fn validate_subtree(&self, node_index: usize, len: usize) -> bool {
+        if len <= 1 {
+            return true;
+        }
+
+        let vantage = &self.items[node_index];
+        let (inner, outer) = self.threshold_bounds(node_index);
+        let len_left = (len - 1) / 2;
+        let left = node_index + 1..node_index + 1 + len_left;
+        let right = node_index + 1 + len_left..node_index + len;
+
+        left.clone().all(|i| vantage.distance(&self.items[i]) <= inner)
+            && right.clone().all(|i| vantage.distance(&self.items[i]) >= outer)
+            && self.validate_subtree(left.start, left.len())
+            && self.validate_subtree(right.start, right.len())
+    }
+
+    fn item_pair(&self, i: usize, j: usize) -> (&T, &T) {
+        let len = self.items.len();
+        assert!(i < len && j < len, "index out of bounds: the len is {len} but the indices are {i} and {j}");
+        (&self.items[i], &self.items[j])
+    }
+
+    /// Checks in debug builds that [`Distance::distance_heuristic`] orders sampled items like [`Distance::distance`], since construction relies on it.
+    fn debug_assert_heuristic(items: &[T]) {
+        use std::cmp::Ordering::{Greater, Less};
+
+        const SAMPLES: usize = 256;
+
+        if !cfg!(debug_assertions) || items.len() < 3 {
+            return;
+        }
+
+        let mut rng = fastrand::Rng::with_seed(items.len() as u64);
+        for _ in 0..SAMPLES {
+            let [vantage, a, b] = std::array::from_fn(|_| &items[rng.usize(..items.len())]);
+            let heuristic = vantage.distance_heuristic(a).partial_cmp(&vantage.distance_heuristic(b));
+            let distance = vantage.distance(a).partial_cmp(&vantage.distance(b));
+            assert!(
+                !matches!((heuristic, distance), (Some(Less), Some(Greater)) | (Some(Greater), Some(Less))),
+                "distance_heuristic orders items differently than distance, it has to be a monotonic transformation of distance such as the squared distance",
+            );
+        }
+    }
+
+    fn build_from_points_par(items: &mut[T], nodes: &mut [f64], threads: usize, selection: VpSelection)
+    where 
+        T: Send,
+    {
+        // Small subtrees are built inline, spawning a thread costs more than building them.
+        if threads <= 1 || items.len() < Self::MIN_PARALLEL_LEN {
+            return Self::build_from_points(items, nodes, selection);
+        }
+
+        let (left_slice, right_slice, left_nodes, right_nodes) = Self::internal_build(items, nodes, selection);
+
+        std::thread::scope(|s| {
+            s.spawn(|| Self::build_from_points_par(left_slice, left_nodes, threads / 2 + threads % 2, selection));
+            Self::build_from_points_par(right_slice, right_nodes, threads / 2, selection);
+        });
+    }
+
+    fn build_from_points_limited(items: &mut[T], nodes: &mut [f64], active_threads: &AtomicUsize, max_threads: usize, selection: VpSelection)
+    where 
+        T: Send,
+    {
+        if items.len() < Self::MIN_PARALLEL_LEN {
+            return Self::build_from_points(items, nodes, selection);
+        }
+
+        let (left_slice, right_slice, left_nodes, right_nodes) = Self::internal_build(items, nodes, selection);
+
+        let acquired = active_threads
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |active| (active < max_threads).then_some(active + 1))
+            .is_ok();
+
+        if acquired {
+            std::thread::scope(|s| {
+                s.spawn(|| {
+                    Self::build_from_points_limited(left_slice, left_nodes, active_threads, max_threads, selection);
+                    active_threads.fetch_sub(1, Ordering::AcqRel);
+                });
+                Self::build_from_points_limited(right_slice, right_nodes, active_threads, max_threads, selection);
+            });
+        } else {
+            Self::build_from_points_limited(left_slice, left_nodes, active_threads, max_threads, selection);
+            Self::build_from_points_limited(right_slice, right_nodes, active_threads, max_threads, selection);
+        }
+    }
+
+    fn build_from_points(items: &mut[T], nodes: &mut [f64], selection: VpSelection) {
+        if items.len() <= 1 {
+            return;
+        }
+
+        let (left_slice, right_slice, left_nodes, right_nodes) = Self::internal_build(items, nodes, selection);
+
+        Self::build_from_points(left_slice, left_nodes, selection);
+        Self::build_from_points(right_slice, right_nodes, selection);
+    }
+
+    #[inline(always)]
+    fn internal_build<'a>(items: &'a mut [T], nodes: &'a mut [f64], selection: VpSelection) -> (&'a mut [T], &'a mut [T], &'a mut [f64], &'a mut [f64]) {
+        let i = match selection {
+            VpSelection::Random => fastrand::usize(..items.len()),
+            VpSelection::Corner => Self::select_corner(items),
+        };
+        items.swap(0, i);
+        let (random_element, slice) = items.split_first_mut().unwrap();
+            
+        // Splitting by position keeps both subtrees balanced, items tied with the threshold end up on either side.
+        let median = slice.len() / 2;
+
+        let (_, median_item, _) = slice.select_nth_unstable_by(median, |a, b| {
+            let dist_a = random_element.distance_heuristic(a);
+            let dist_b = random_element.distance_heuristic(b);
+            dist_a.partial_cmp(&dist_b).unwrap_or(std::cmp::Ordering::Less)
+        });
+
+        nodes[0] = random_element.distance(median_item);
+        debug_assert!(nodes[0] >= 0.0 || nodes[0].is_nan(), "distance returned a negative value ({}) between the vantage point and the median item of a subtree with {} items, distances must be non-negative", nodes[0], slice.len() + 1);
+
+        let (left_slice, right_slice) = slice.split_at_mut(median);
+        let (left_nodes, right_nodes) = nodes[1..].split_at_mut(median);
+
+        (left_slice, right_slice, left_nodes, right_nodes)
+    }
+
+    fn select_corner(items: &[T]) -> usize {
+        const SAMPLE_SIZE: usize = 16;
+
+        if items.len() <= SAMPLE_SIZE {
+            return fastrand::usize(..items.len());
+        }
+
+        let sample: [usize; SAMPLE_SIZE] = std::array::from_fn(|_| fastrand::usize(..items.len()));
+
+        (0..items.len().isqrt())
+            .map(|_| fastrand::usize(..items.len()))
+            .map(|candidate| {
+                let distances = sample.map(|i| items[candidate].distance(&items[i]));
+                let mean = distances.iter().sum::<f64>() / SAMPLE_SIZE as f64;
+                let variance = distances.iter().map(|d| (d - mean) * (d - mean)).sum::<f64>();
+                (candidate, variance)
+            })
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Less))
+            .map_or(0, |(candidate, _)| candidate)
+    }
+
+    pub(crate) fn search_items<U: Distance<T>>(&self, target: &U, query: &Query) -> Vec<HeapItem> {
+        self.index_pending();
+        self.search_items_by(target, query, &|item| item)
+    }
+
+    pub(crate) fn search_nearest<U: Distance<T>>(&self, target: &U, exclusive: bool) -> Option<usize> {
+        let mut collector = NearestCollector::new(exclusive);
+        self.search_with(target, &mut collector);
+        collector.best_index
+    }
+
+    fn search<U: Distance<T>>(&self, target: &U, query: &Query) -> BinaryHeap<HeapItem> {
+        self.index_pending();
+        self.search_by(target, query, &|item| item)
+    }
+
+    fn collect_heap(&self, heap: BinaryHeap<HeapItem>, sorted: bool) -> Vec<&T> {
+        Self::heap_items(heap, sorted)
+            .into_iter()
+            .map(|item| &self.items[item.index])
+            .collect()
+    }
+
+    pub(crate) fn search_with<U: Distance<T>, C: Collector>(&self, target: &U, collector: &mut C) {
+        self.index_pending();
+        self.search_with_by(target, collector, &|item| item);
+    }
+
+    /// Indexes the items appended since the last rebuild unless they are indexed already, see [`Self::append`].
+    fn index_pending(&self) {
+        if self.pending.len == 0 {
+            return;
+        }
+        self.pending.tree.get_or_init(|| {
+            let start = self.indexed_len();
+            let appended = self.items[start..start + self.pending.len].iter()
+                .enumerate()
+                .map(|(index, item)| Indexed { item, index })
+                .collect();
+            Box::new(VpTree::new(appended).map_items(|indexed| indexed.index))
+        });
+    }
+}
+
+impl<T> VpTree<T> {
+    const ROOT: usize = 0;
+
+    /// Returns a reference to all items stored in the VpTree. The items are stored in an arbitrary order.
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+
+    /// Transforms every item, for example its payload, while keeping the tree structure instead of rebuilding it.
+    /// 
+    /// The caller has to make sure distances between the transformed items match the original ones, otherwise queries return incorrect results.
+    /// For multiple threads, use [`Self::map_payload_parallel`].
+    pub fn map_payload<V, F: FnMut(T) -> V>(self, f: F) -> VpTree<V> {
+        self.map_items(f)
+    }
+
+    /// Transforms every item using multiple threads while keeping the tree structure. See [`Self::map_payload`].
+    pub fn map_payload_parallel<V, F>(mut self, f: F, threads: usize) -> VpTree<V>
+    where
+        T: Send,
+        V: Send,
+        F: Fn(T) -> V + Sync,
+    {
+        let mut items = std::mem::take(&mut self.items);
+        let chunk_size = items.len().div_ceil(threads.max(1)).max(1);
+        let mut chunks = Vec::new();
+        while items.len() > chunk_size {
+            chunks.push(items.split_off(items.len() - chunk_size));
+        }
+        chunks.push(items);
+
+        let f = &f;
+        let mapped = std::thread::scope(|s| {
+            let handles = chunks.into_iter()
+                .rev()
+                .map(|chunk| s.spawn(move || chunk.into_iter().map(f).collect::<Vec<V>>()))
+                .collect::<Vec<_>>();
+            handles.into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        });
+        self.with_items(mapped)
+    }
+
+    /// Transforms every item while keeping the tree structure. The caller has to make sure distances between the transformed items match the original ones.
+    pub(crate) fn map_items<V, F: FnMut(T) -> V>(mut self, f: F) -> VpTree<V> {
+        let items = std::mem::take(&mut self.items).into_iter().map(f).collect();
+        self.with_items(items)
+    }
+
+    /// Replaces the items by items in the same order, keeping the tree structure.
+    fn with_items<V>(self, items: Vec<V>) -> VpTree<V> {
+        VpTree {
+            items,
+            nodes: self.nodes,
+            #[cfg(feature = "compact-thresholds")]
+            compact_nodes: self.compact_nodes,
+            rebuild_policy: self.rebuild_policy,
+            mutations: self.mutations,
+            modified: self.modified,
+            pending: self.pending,
+            selection: self.selection,
+            distance_calls: self.distance_calls,
+        }
+    }
+    /// Returns the number of items that are part of the tree structure, one per threshold.
+    #[inline(always)]
+    fn indexed_len(&self) -> usize {
+        #[cfg(feature = "compact-thresholds")]
+        if let Some(compact) = &self.compact_nodes {
+            return compact.len();
+        }
+        self.nodes.len()
+    }
+
+    /// Returns bounds `(inner, outer)` of the threshold of a node: items of the left subtree are within `inner`, items of the right subtree at least `outer` away.
+    /// Both are the threshold itself unless the thresholds are compacted.
+    #[inline(always)]
+    fn threshold_bounds(&self, node_index: usize) -> (f64, f64) {
+        #[cfg(feature = "compact-thresholds")]
+        if let Some(compact) = &self.compact_nodes {
+            let threshold = compact[node_index];
+            return (threshold as f64, threshold.next_down() as f64);
+        }
+        let threshold = self.nodes[node_index];
+        (threshold, threshold)
+    }
+
+    fn heap_items(heap: BinaryHeap<HeapItem>, sorted: bool) -> Vec<HeapItem> {
+        if sorted {
+            heap.into_sorted_vec()
+        } else {
+            heap.into_vec()
+        }
+    }
+
+    /// Searches the tree, measuring distances to the item `resolve` returns for every stored item.
+    /// This lets a tree of indices search the items they refer to, see [`crate::VpTreeInOrder`]. The returned indices are positions in the tree.
+    pub(crate) fn search_items_by<'a, X, U, R>(&'a self, target: &U, query: &Query, resolve: &R) -> Vec<HeapItem>
+    where
+        X: Distance<X> + 'a,
+        U: Distance<X>,
+        R: Fn(&'a T) -> &'a X,
+    {
+        // The single nearest neighbor is tracked without a heap
+        if query.max_items == 1 && query.max_distance == f64::INFINITY && query.boundary_epsilon == 0.0 && !query.exclusive_boundary && !query.collapse_duplicates {
+            let mut collector = NearestCollector::new(query.exclusive);
+            self.search_with_by(target, &mut collector, resolve);
+            return collector.best_index
+                .map(|index| HeapItem { index, distance: collector.best_distance })
+                .into_iter()
+                .collect();
+        }
+        #[cfg(feature = "inline-knn")]
+        if query.max_items <= INLINE_KNN_LEN && !query.collapse_duplicates {
+            let mut collector = InlineKnnCollector::<{ INLINE_KNN_LEN }>::new(query);
+            self.search_with_by(target, &mut collector, resolve);
+            return collector.into_vec();
+        }
+        Self::heap_items(self.search_by(target, query, resolve), query.sorted)
+    }
+
+    fn search_by<'a, X, U, R>(&'a self, target: &U, query: &Query, resolve: &R) -> BinaryHeap<HeapItem>
+    where
+        X: Distance<X> + 'a,
+        U: Distance<X>,
+        R: Fn(&'a T) -> &'a X,
+    {
+        if query.collapse_duplicates {
+            return self.search_collapsed_by(target, query, resolve);
+        }
+        let mut collector = KnnCollector::new(query);
+        self.search_with_by(target, &mut collector, resolve);
+        collector.heap
+    }
+
+    /// Searches twice as many candidates until enough distinct items remain after removing duplicates, or all candidates are found.
+    fn search_collapsed_by<'a, X, U, R>(&'a self, target: &U, query: &Query, resolve: &R) -> BinaryHeap<HeapItem>
+    where
+        X: Distance<X> + 'a,
+        U: Distance<X>,
+        R: Fn(&'a T) -> &'a X,
+    {
+        let mut candidates = Query { collapse_duplicates: false, sorted: true, ..query.clone() };
+
+        loop {
+            let items = self.search_items_by(target, &candidates, resolve);
+            let mut distinct: Vec<HeapItem> = Vec::new();
+            for item in &items {
+                if distinct.len() == query.max_items {
+                    break;
+                }
+                let duplicate = distinct.iter()
+                    .rev()
+                    .take_while(|kept| kept.distance == item.distance)
+                    .any(|kept| resolve(&self.items[kept.index]).distance(resolve(&self.items[item.index])) == 0.0);
+                if !duplicate {
+                    distinct.push(*item);
+                }
+            }
+
+            if distinct.len() == query.max_items || items.len() < candidates.max_items {
+                return distinct.into();
+            }
+            candidates.max_items = candidates.max_items.saturating_mul(2);
+        }
+    }
+
+    pub(crate) fn search_with_by<'a, X, U, C, R>(&'a self, target: &U, collector: &mut C, resolve: &R)
+    where
+        X: 'a,
+        U: Distance<X>,
+        C: Collector,
+        R: Fn(&'a T) -> &'a X,
+    {
+        if self.search_rec(Self::ROOT, self.indexed_len(), target, collector, resolve).is_continue() {
+            let _ = self.search_unindexed(target, collector, resolve);
+        }
+    }
+
+    fn search_unindexed<'a, X, U, C, R>(&'a self, target: &U, collector: &mut C, resolve: &R) -> ControlFlow<()>
+    where
+        X: 'a,
+        U: Distance<X>,
+        C: Collector,
+        R: Fn(&'a T) -> &'a X,
+    {
+        let indexed_len = self.indexed_len();
+        let mut unindexed = indexed_len..self.items.len();
+        if let Some(pending) = self.pending.tree.get() {
+            let mut collector = OffsetCollector::new(collector, pending.items(), indexed_len, &self.distance_calls);
+            pending.search_rec(Self::ROOT, pending.indexed_len(), target, &mut collector, &|offset| resolve(&self.items[indexed_len + *offset]))?;
+            unindexed.start += pending.items.len();
+        }
+
+        for index in unindexed {
+            let item = resolve(&self.items[index]);
+            if target.cheap_lower_bound(item) > collector.tau() {
+                continue;
+            }
+            let dist = target.distance(item);
+            self.distance_calls.increment();
+            debug_assert!(dist >= 0.0 || dist.is_nan(), "distance returned a negative value ({dist}) between the target and the item at index {index}, distances must be non-negative");
+            collector.visit(index, dist)?;
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn search_rec<'a, X, U, C, R>(
+        &'a self,
+        node_index: usize,
+        len: usize,
+        target: &U,
+        collector: &mut C,
+        resolve: &R,
+    ) -> ControlFlow<()>
+    where
+        X: 'a,
+        U: Distance<X>,
+        C: Collector,
+        R: Fn(&'a T) -> &'a X,
+    {
+        if len == 0 {
+            return ControlFlow::Continue(());
+        }
+
+        let (inner, outer) = self.threshold_bounds(node_index);
+
+        let left = node_index + 1;
+        let right = node_index + 1 + (len - 1) / 2;
+        let len_left = (len - 1) / 2;
+        let right_len = len - 1 - len_left;
+
+        let item = resolve(&self.items[node_index]);
+        let lower_bound = target.cheap_lower_bound(item);
+        if lower_bound > collector.tau() {
+            // The true distance is unknown but at least the lower bound, the right subtree can not be excluded.
+            self.search_rec(right, right_len, target, collector, resolve)?;
+            if lower_bound - collector.tau() <= inner {
+                self.search_rec(left, len_left, target, collector, resolve)?;
+            }
+            return ControlFlow::Continue(());
+        }
+
+        let dist = target.distance(item);
+        self.distance_calls.increment();
+        debug_assert!(dist >= 0.0 || dist.is_nan(), "distance returned a negative value ({dist}) between the target and the item at index {node_index}, distances must be non-negative");
+
+        collector.visit(node_index, dist)?;
+
+        if dist <= inner {
+            self.search_rec(left, len_left, target, collector, resolve)?;
+            if dist + collector.tau() >= outer {
+                self.search_rec(right, right_len, target, collector, resolve)?;
+            }
+        } else {
+            self.search_rec(right, right_len, target, collector, resolve)?;
+            if dist - collector.tau() <= inner {
+                self.search_rec(left, len_left, target, collector, resolve)?;
+            }
+        }
+
+        ControlFlow::Continue(())
+    }
+
+}
+
+impl<T> Default for VpTree<T> {
+    /// Creates an empty [`VpTree`].
+    fn default() -> Self {
+        VpTree {
+            items: Vec::new(),
+            nodes: Vec::new(),
+            #[cfg(feature = "compact-thresholds")]
+            compact_nodes: None,
+            rebuild_policy: RebuildPolicy::Never,
+            mutations: 0,
+            modified: Vec::new(),
+            pending: PendingIndex::default(),
+            selection: VpSelection::Random,
+            distance_calls: DistanceCounter::default(),
+        }
+    }
+}
+
+impl<T: Distance<T>> FromIterator<T> for VpTree<T> {
+    /// Constructs a new [`VpTree`] from an iterator of items. The items are consumed and stored within the tree.
+    /// This constructor uses a single thread. For parallel construction, use [`Self::new_parallel`].
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let items: Vec<T> = iter.into_iter().collect();
+        VpTree::new(items)
+    }
+}
+
+/// Mutable access to the items of a [`VpTree`] that records which items were accessed.
+/// Created by [`VpTree::items_mut_tracked`].
+pub struct TrackedItemsMut<'a, T> {
+    items: &'a mut Vec<T>,
+    modified: &'a mut Vec<usize>,
+}
+
+impl<T> TrackedItemsMut<'_, T> {
+    /// Returns a mutable reference to the item at `index`, or `None` if the index is out of bounds.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        let item = self.items.get_mut(index)?;
+        self.modified.push(index);
+        Some(item)
+    }
+
+    /// Returns the number of stored items.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns `true` if no items are stored.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+/// Handle to a node of a [`VpTree`], obtained through [`VpTree::root_node`] and the child accessors.
+/// 
+/// Every node holds one item as its vantage point. Handles are invalidated by [`VpTree::rebuild`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    index: usize,
+    len: usize,
+}
+
+impl NodeId {
+    fn new(index: usize, len: usize) -> Option<Self> {
+        (len > 0).then_some(NodeId { index, len })
+    }
+
+    /// Returns the index of the vantage point of the node, see [`VpTree::item`].
+    pub fn index(&self) -> usize {
+        self.index
     }
-}
 
-impl Ord for HeapItem {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.distance.partial_cmp(&other.distance).unwrap_or(std::cmp::Ordering::Less)
+    /// Returns the number of items in the subtree rooted at the node, including the node itself.
+    pub fn subtree_len(&self) -> usize {
+        self.len
     }
 }
@@ -3,7 +3,7 @@
 mod tests {
     use std::collections::BinaryHeap;
 
-    use vp_tree::{Distance, Querry, VpTree};
+    use vp_tree::{Distance, Query, VpTree};
 
     #[test]
     fn test_nn() {
@@ -58,13 +58,13 @@ mod tests {
         let vp_tree = VpTree::new(points);
 
         let target = TestPoint { value: 3.4 };
-        let nearest = vp_tree.querry(&target, vp_tree::Querry::k_nearest_neighbors(2).sorted());
+        let nearest = vp_tree.query(&target, vp_tree::Query::k_nearest_neighbors(2).sorted());
 
         assert_eq!(nearest.len(), 2);
         assert_eq!(nearest[0].value, 3.0);
         assert_eq!(nearest[1].value, 4.0);
 
-        let nearest_exclusive = vp_tree.querry(&target,Querry::k_nearest_neighbors(2).exclusive().sorted());
+        let nearest_exclusive = vp_tree.query(&target,Query::k_nearest_neighbors(2).exclusive().sorted());
 
         assert_eq!(nearest_exclusive.len(), 2);
         assert_eq!(nearest_exclusive[0].value, 3.0);
@@ -86,7 +86,7 @@ mod tests {
         let vp_tree = VpTree::new(points);
 
         let target = TestPoint { value: 3.5 };
-        let nearest = vp_tree.querry(&target, vp_tree::Querry::k_nearest_neighbors(1));
+        let nearest = vp_tree.query(&target, vp_tree::Query::k_nearest_neighbors(1));
         assert_eq!(nearest.len(), 0);
     }
 
@@ -117,7 +117,7 @@ mod tests {
             let vp_tree = VpTree::new(points.clone());
             
             let target = TestPoint { x: 500.0, y: 500.0 };
-            let nearest = vp_tree.querry(&target, Querry::k_nearest_neighbors(10).sorted());
+            let nearest = vp_tree.query(&target, Query::k_nearest_neighbors(10).sorted());
             
             let baseline_nearest = baseline_linear_search(&points, &target, 10);
             
@@ -180,7 +180,7 @@ mod tests {
             let vp_tree = VpTree::new(points.clone());
             
             let target = TestPoint { value: 500.0 };
-            let nearest = vp_tree.querry(&target, Querry::k_nearest_neighbors(10).sorted());
+            let nearest = vp_tree.query(&target, Query::k_nearest_neighbors(10).sorted());
             
             let baseline_nearest = baseline_linear_search(&points, &target, 10);
             
@@ -209,9 +209,9 @@ mod tests {
 
         let target = TestPoint { value: 500.0 };
         let radius = 10.0;
-        let results: Vec<_> = vp_tree.querry(
+        let results: Vec<_> = vp_tree.query(
             &target,
-            Querry::neighbors_within_radius(radius).sorted(),
+            Query::neighbors_within_radius(radius).sorted(),
         );
 
         let expected: Vec<_> = points
@@ -225,6 +225,3435 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_item_accessor() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            value: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                (self.value - other.value).abs()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..10)
+            .map(|i| TestPoint { value: i as f64 })
+            .collect();
+
+        let vp_tree = VpTree::new(points);
+
+        for (index, item) in vp_tree.items().iter().enumerate() {
+            assert_eq!(vp_tree.item(index), Some(item));
+        }
+        assert_eq!(vp_tree.item(10), None);
+        assert_eq!(vp_tree.item(usize::MAX), None);
+    }
+
+    #[test]
+    fn test_distance_between() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                self.distance_heuristic(other).sqrt()
+            }
+
+            fn distance_heuristic(&self, other: &TestPoint) -> f64 {
+                let dx = self.x - other.x;
+                let dy = self.y - other.y;
+                dx * dx + dy * dy
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..20)
+            .map(|_| TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 })
+            .collect();
+
+        let vp_tree = VpTree::new(points);
+        let items = vp_tree.items();
+
+        for i in 0..items.len() {
+            for j in 0..items.len() {
+                assert_eq!(vp_tree.distance_between(i, j), items[i].distance(&items[j]));
+                assert_eq!(vp_tree.distance_heuristic_between(i, j), items[i].distance_heuristic(&items[j]));
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "index out of bounds")]
+    fn test_distance_between_out_of_bounds() {
+        #[derive(Debug)]
+        struct TestPoint {
+            value: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                (self.value - other.value).abs()
+            }
+        }
+
+        let vp_tree = VpTree::new(vec![TestPoint { value: 1.0 }, TestPoint { value: 2.0 }]);
+        vp_tree.distance_between(0, 2);
+    }
+
+    #[test]
+    fn test_query_sorted_by() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            value: f64,
+            popularity: u32,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                (self.value - other.value).abs()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..1000)
+            .map(|_| TestPoint { value: fastrand::f64() * 1000.0, popularity: fastrand::u32(..100) })
+            .collect();
+
+        let vp_tree = VpTree::new(points);
+        let target = TestPoint { value: 500.0, popularity: 0 };
+        let query = Query::k_nearest_neighbors(20);
+
+        let by_distance = vp_tree.query_sorted_by(&target, &query, |_, distance| distance.to_bits());
+        let sorted = vp_tree.query(&target, query.clone().sorted());
+        assert_eq!(by_distance, sorted);
+
+        let by_popularity = vp_tree.query_sorted_by(&target, &query, |item, _| std::cmp::Reverse(item.popularity));
+        assert_eq!(by_popularity.len(), 20);
+        assert!(by_popularity.windows(2).all(|w| w[0].popularity >= w[1].popularity));
+        for item in by_popularity {
+            assert!(sorted.contains(&item));
+        }
+    }
+
+    #[test]
+    fn test_shared_snapshot() {
+        use std::sync::{Arc, RwLock};
+
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            value: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                (self.value - other.value).abs()
+            }
+        }
+
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<VpTree<TestPoint>>();
+        assert_send_sync::<Arc<VpTree<TestPoint>>>();
+
+        let old_points: Vec<TestPoint> = (0..1000)
+            .map(|i| TestPoint { value: i as f64 })
+            .collect();
+        let new_points: Vec<TestPoint> = (0..1000)
+            .map(|i| TestPoint { value: i as f64 + 0.5 })
+            .collect();
+
+        let snapshot = Arc::new(RwLock::new(Arc::new(VpTree::new(old_points))));
+
+        std::thread::scope(|s| {
+            let readers = (0..4)
+                .map(|_| {
+                    let vp_tree = Arc::clone(&snapshot.read().unwrap());
+                    s.spawn(move || {
+                        for _ in 0..100 {
+                            let target = TestPoint { value: fastrand::f64() * 1000.0 };
+                            let nearest = vp_tree.nearest_neighbor(&target).unwrap();
+                            assert_eq!(nearest.value.fract(), 0.0);
+                            let k_nearest = vp_tree.query(&target, Query::k_nearest_neighbors(3));
+                            assert_eq!(k_nearest.len(), 3);
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            let replacement = Arc::new(VpTree::new_parallel(new_points, 4));
+            *snapshot.write().unwrap() = replacement;
+
+            for reader in readers {
+                reader.join().unwrap();
+            }
+        });
+
+        let vp_tree = Arc::clone(&snapshot.read().unwrap());
+        let nearest = vp_tree.nearest_neighbor(&TestPoint { value: 10.4 }).unwrap();
+        assert_eq!(nearest.value, 10.5);
+    }
+
+    #[test]
+    fn test_query_scan() {
+        use std::ops::ControlFlow;
+
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            value: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                (self.value - other.value).abs()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..1000)
+            .map(|_| TestPoint { value: fastrand::f64() * 1000.0 })
+            .collect();
+
+        let vp_tree = VpTree::new(points);
+        let target = TestPoint { value: 500.0 };
+
+        for query in [
+            Query::neighbors_within_radius(50.0),
+            Query::k_nearest_neighbors(10),
+            Query::k_nearest_neighbors_within_radius(10, 3.0).exclusive(),
+        ] {
+            let mut visited = Vec::new();
+            vp_tree.query_scan(&target, &query, |item, distance| {
+                assert_eq!(distance, target.distance(item));
+                visited.push(item);
+                ControlFlow::Continue(())
+            });
+
+            let mut expected = vp_tree.query(&target, &query);
+            visited.sort_by(|a, b| a.value.partial_cmp(&b.value).unwrap());
+            expected.sort_by(|a, b| a.value.partial_cmp(&b.value).unwrap());
+            assert_eq!(visited, expected);
+        }
+
+        let mut count = 0;
+        vp_tree.query_scan(&target, Query::neighbors_within_radius(50.0), |_, _| {
+            count += 1;
+            if count == 3 { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+        });
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn test_query_capped() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            value: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                (self.value - other.value).abs()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..1000)
+            .map(|_| TestPoint { value: fastrand::f64() * 1000.0 })
+            .collect();
+
+        let vp_tree = VpTree::new(points);
+        let target = TestPoint { value: 500.0 };
+        let query = Query::k_nearest_neighbors(10).sorted();
+
+        let (results, incomplete) = vp_tree.query_capped(&target, &query, 1000);
+        assert!(!incomplete);
+        assert_eq!(results, vp_tree.query(&target, &query));
+
+        let (results, incomplete) = vp_tree.query_capped(&target, &query, 5);
+        assert!(incomplete);
+        assert!(results.len() <= 5);
+
+        let (results, incomplete) = vp_tree.query_capped(&target, &query, 0);
+        assert!(incomplete);
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn test_rebuild_policy() {
+        use vp_tree::RebuildPolicy;
+
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            value: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                (self.value - other.value).abs()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..100)
+            .map(|_| TestPoint { value: fastrand::f64() * 1000.0 })
+            .collect();
+        let mut all_points = points.clone();
+
+        let mut vp_tree = VpTree::new(points);
+        let built_depth = vp_tree.depth();
+        assert_eq!(built_depth, vp_tree.expected_depth());
+
+        for _ in 0..50 {
+            let point = TestPoint { value: fastrand::f64() * 1000.0 };
+            all_points.push(point.clone());
+            vp_tree.insert(point);
+        }
+        assert_eq!(vp_tree.depth(), built_depth + 50);
+
+        let target = TestPoint { value: 500.0 };
+        assert_eq!(
+            vp_tree.query(&target, Query::k_nearest_neighbors(10).sorted()),
+            baseline_linear_search(&all_points, &target, 10),
+        );
+
+        vp_tree.set_rebuild_policy(RebuildPolicy::AfterMutations(20));
+        assert_eq!(vp_tree.depth(), vp_tree.expected_depth());
+        for i in 1..=40 {
+            let point = TestPoint { value: fastrand::f64() * 1000.0 };
+            all_points.push(point.clone());
+            vp_tree.insert(point);
+            if i % 20 == 0 {
+                assert_eq!(vp_tree.depth(), vp_tree.expected_depth());
+            } else {
+                assert!(vp_tree.depth() > vp_tree.expected_depth());
+            }
+        }
+
+        vp_tree.set_rebuild_policy(RebuildPolicy::WhenImbalanceExceeds(2.0));
+        for _ in 0..100 {
+            let point = TestPoint { value: fastrand::f64() * 1000.0 };
+            all_points.push(point.clone());
+            vp_tree.insert(point);
+            assert!(vp_tree.depth() <= 2 * vp_tree.expected_depth());
+        }
+
+        assert_eq!(vp_tree.items().len(), all_points.len());
+        assert_eq!(
+            vp_tree.query(&target, Query::k_nearest_neighbors(10).sorted()),
+            baseline_linear_search(&all_points, &target, 10),
+        );
+    }
+
+    #[test]
+    fn test_all_nearest() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            value: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                (self.value - other.value).abs()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..100)
+            .map(|i| TestPoint { value: i as f64 })
+            .collect();
+        let vp_tree = VpTree::new(points);
+
+        let mut tied = vp_tree.all_nearest(&TestPoint { value: 41.5 });
+        tied.sort_by(|a, b| a.value.partial_cmp(&b.value).unwrap());
+        assert_eq!(tied, vec![&TestPoint { value: 41.0 }, &TestPoint { value: 42.0 }]);
+
+        assert_eq!(vp_tree.all_nearest(&TestPoint { value: 41.2 }), vec![&TestPoint { value: 41.0 }]);
+
+        let empty: VpTree<TestPoint> = VpTree::new(vec![]);
+        assert!(empty.all_nearest(&TestPoint { value: 0.0 }).is_empty());
+    }
+
+    #[test]
+    fn test_nearest_neighbor_tie_break() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            value: f64,
+            label: u32,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                (self.value - other.value).abs()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..100)
+            .map(|i| TestPoint { value: i as f64, label: 0 })
+            .collect();
+        let mut vp_tree = VpTree::new(points);
+        for label in 1..=3 {
+            vp_tree.insert(TestPoint { value: 42.0, label });
+        }
+
+        let target = TestPoint { value: 42.2, label: 0 };
+        assert_eq!(vp_tree.nearest_neighbor_tie_break(&target, usize::max).unwrap().label, 3);
+
+        let index = vp_tree.items().iter().position(|item| item.value == 42.0).unwrap();
+        assert_eq!(vp_tree.nearest_neighbor_tie_break(&target, usize::min), Some(&vp_tree.items()[index]));
+
+        let target = TestPoint { value: 41.5, label: 0 };
+        let highest = vp_tree.nearest_neighbor_tie_break(&target, usize::max).unwrap();
+        assert_eq!(highest, &TestPoint { value: 42.0, label: 3 });
+
+        let empty: VpTree<TestPoint> = VpTree::new(vec![]);
+        assert!(empty.nearest_neighbor_tie_break(&target, usize::max).is_none());
+    }
+
+    #[test]
+    fn test_revalidate() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            value: f64,
+            label: u32,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                (self.value - other.value).abs()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..1000)
+            .map(|i| TestPoint { value: i as f64, label: 0 })
+            .collect();
+
+        let mut vp_tree = VpTree::new(points);
+
+        let mut items = vp_tree.items_mut_tracked();
+        for index in 0..items.len() {
+            items.get_mut(index).unwrap().label = 1;
+        }
+        assert!(items.get_mut(1000).is_none());
+        assert!(vp_tree.revalidate());
+
+        let index = vp_tree.items().iter().position(|p| p.value == 0.0).unwrap();
+        vp_tree.items_mut_tracked().get_mut(index).unwrap().value = 999.5;
+        assert!(!vp_tree.revalidate());
+
+        vp_tree.rebuild();
+        assert!(vp_tree.revalidate());
+        let nearest = vp_tree.nearest_neighbor(&TestPoint { value: 999.6, label: 0 }).unwrap();
+        assert_eq!(nearest.value, 999.5);
+    }
+
+    #[test]
+    fn test_hamming() {
+        use vp_tree::metrics::Hamming;
+
+        let fingerprints: Vec<Hamming<32>> = (0..2000)
+            .map(|_| Hamming([(); 32].map(|_| fastrand::u8(..))))
+            .collect();
+
+        let vp_tree = VpTree::new(fingerprints.clone());
+
+        for _ in 0..20 {
+            let mut target = fingerprints[fastrand::usize(..fingerprints.len())];
+            for _ in 0..fastrand::usize(..40) {
+                target.0[fastrand::usize(..32)] ^= 1 << fastrand::u8(..8);
+            }
+
+            for radius in [0.0, 20.0, 110.0, 128.0] {
+                let mut results = vp_tree.query(&target, Query::neighbors_within_radius(radius));
+                let mut expected: Vec<_> = fingerprints.iter()
+                    .filter(|f| {
+                        let bits: u32 = f.0.iter().zip(target.0.iter()).map(|(a, b)| (a ^ b).count_ones()).sum();
+                        bits as f64 <= radius
+                    })
+                    .collect();
+                results.sort_by_key(|f| f.0);
+                expected.sort_by_key(|f| f.0);
+                assert_eq!(results, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parallel_with_limit() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                self.distance_heuristic(other).sqrt()
+            }
+
+            fn distance_heuristic(&self, other: &TestPoint) -> f64 {
+                let dx = self.x - other.x;
+                let dy = self.y - other.y;
+                dx * dx + dy * dy
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..50_000)
+            .map(|_| TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 })
+            .collect();
+
+        for max_threads in [0, 1, 3, 8] {
+            let vp_tree = VpTree::new_parallel_with_limit(points.clone(), max_threads);
+            assert_eq!(vp_tree.items().len(), points.len());
+
+            for _ in 0..10 {
+                let target = TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 };
+                let nearest = vp_tree.query(&target, Query::k_nearest_neighbors(5).sorted());
+                assert_eq!(nearest, baseline_linear_search(&points, &target, 5));
+            }
+        }
+    }
+
+    #[test]
+    fn test_exists_within() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            value: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                (self.value - other.value).abs()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..100)
+            .map(|i| TestPoint { value: i as f64 * 10.0 })
+            .collect();
+        let vp_tree = VpTree::new(points);
+
+        assert!(vp_tree.exists_within(&TestPoint { value: 500.0 }, 0.0));
+        assert!(vp_tree.exists_within(&TestPoint { value: 503.0 }, 3.0));
+        assert!(!vp_tree.exists_within(&TestPoint { value: 503.0 }, 2.5));
+        assert!(vp_tree.exists_within(&TestPoint { value: 505.0 }, 5.0));
+        assert!(!vp_tree.exists_within(&TestPoint { value: 505.0 }, 4.999));
+        assert!(!vp_tree.exists_within(&TestPoint { value: -1.0 }, 0.5));
+        assert!(vp_tree.exists_within(&TestPoint { value: 2000.0 }, f64::INFINITY));
+
+        let empty: VpTree<TestPoint> = VpTree::new(vec![]);
+        assert!(!empty.exists_within(&TestPoint { value: 0.0 }, f64::INFINITY));
+    }
+
+    #[test]
+    fn test_query_with_paths() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            value: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                (self.value - other.value).abs()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..1000)
+            .map(|_| TestPoint { value: fastrand::f64() * 1000.0 })
+            .collect();
+        let vp_tree = VpTree::new(points);
+        let target = TestPoint { value: 500.0 };
+        let query = Query::k_nearest_neighbors(10).sorted();
+
+        let results = vp_tree.query_with_paths(&target, &query);
+        let items: Vec<_> = results.iter().map(|(item, _)| *item).collect();
+        assert_eq!(items, vp_tree.query(&target, &query));
+
+        let root = &vp_tree.items()[0];
+        let all = vp_tree.query_with_paths(&target, Query::default());
+        let left = all.iter().filter(|(_, path)| path.first() == Some(&false)).map(|(item, _)| root.distance(*item));
+        let right = all.iter().filter(|(_, path)| path.first() == Some(&true)).map(|(item, _)| root.distance(*item));
+        assert!(left.fold(0.0, f64::max) <= right.fold(f64::INFINITY, f64::min));
+
+        let mut paths: Vec<_> = all.into_iter().map(|(_, path)| path).collect();
+        assert!(paths.iter().all(|path| path.len() < vp_tree.depth()));
+        assert_eq!(paths.iter().filter(|path| path.is_empty()).count(), 1);
+        paths.sort();
+        paths.dedup();
+        assert_eq!(paths.len(), 1000);
+
+        assert!(results.iter().all(|(_, path)| path.len() < vp_tree.depth()));
+    }
+
+    #[test]
+    fn test_corner_selection() {
+        use vp_tree::VpSelection;
+
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            cords: [f64; 5],
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                self.distance_heuristic(other).sqrt()
+            }
+
+            fn distance_heuristic(&self, other: &TestPoint) -> f64 {
+                self.cords.iter().zip(other.cords.iter())
+                    .map(|(a, b)| (a - b) * (a - b))
+                    .sum()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..5000)
+            .map(|_| TestPoint { cords: [(); 5].map(|_| fastrand::f64() * 1000.0) })
+            .collect();
+
+        let mut vp_tree = VpTree::new_with_selection(points.clone(), VpSelection::Corner);
+        for _ in 0..2 {
+            for _ in 0..20 {
+                let target = TestPoint { cords: [(); 5].map(|_| fastrand::f64() * 1000.0) };
+                let nearest = vp_tree.query(&target, Query::k_nearest_neighbors(10).sorted());
+                assert_eq!(nearest, baseline_linear_search(&points, &target, 10));
+            }
+            vp_tree.rebuild();
+        }
+    }
+
+    #[test]
+    fn test_rebuild_with_selection() {
+        use vp_tree::VpSelection;
+
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            cords: [f64; 5],
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                self.cords.iter().zip(other.cords.iter())
+                    .map(|(a, b)| (a - b) * (a - b))
+                    .sum::<f64>()
+                    .sqrt()
+            }
+        }
+
+        let random_point = || TestPoint { cords: [(); 5].map(|_| fastrand::f64() * 1000.0) };
+        let points: Vec<TestPoint> = (0..5000).map(|_| random_point()).collect();
+        let mut vp_tree = VpTree::new(points[..4000].to_vec());
+        for point in &points[4000..] {
+            vp_tree.insert(point.clone());
+        }
+
+        vp_tree.rebuild_with_selection(VpSelection::Corner);
+        let stats = vp_tree.stats();
+        assert_eq!(stats.len, points.len());
+        assert_eq!(stats.imbalance_ratio, 1.0);
+
+        for _ in 0..20 {
+            let target = random_point();
+            let nearest = vp_tree.query(&target, Query::k_nearest_neighbors(10).sorted());
+            assert_eq!(nearest, baseline_linear_search(&points, &target, 10));
+        }
+    }
+
+    #[test]
+    fn test_payload_tree() {
+        use vp_tree::VpTreeWithPayload;
+
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..2000)
+            .map(|_| TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 })
+            .collect();
+        let payloads: Vec<String> = points.iter()
+            .map(|p| format!("{}:{}", p.x, p.y))
+            .collect();
+
+        let vp_tree = VpTreeWithPayload::new(points.clone(), payloads);
+        for (point, payload) in vp_tree.tree().items().iter().zip(vp_tree.payloads()) {
+            assert_eq!(*payload, format!("{}:{}", point.x, point.y));
+        }
+
+        for _ in 0..50 {
+            let target = TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 };
+            let result = vp_tree.query(&target, Query::k_nearest_neighbors(10).sorted());
+            let expected = baseline_linear_search(&points, &target, 10);
+            assert_eq!(result.iter().map(|(point, _)| *point).collect::<Vec<_>>(), expected);
+            for (point, payload) in result {
+                assert_eq!(*payload, format!("{}:{}", point.x, point.y));
+            }
+
+            let (nearest, payload) = vp_tree.nearest_neighbor(&target).unwrap();
+            assert_eq!(nearest, expected[0]);
+            assert_eq!(*payload, format!("{}:{}", nearest.x, nearest.y));
+        }
+    }
+
+    #[test]
+    fn test_fold_k_nearest() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..2000)
+            .map(|_| TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 })
+            .collect();
+        let vp_tree = VpTree::new(points.clone());
+
+        for _ in 0..50 {
+            let target = TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 };
+            let folded = vp_tree.fold_k_nearest(&target, 10, Vec::new(), |mut acc, item, distance| {
+                assert_eq!(distance, item.distance(&target));
+                acc.push(item);
+                acc
+            });
+            assert_eq!(folded, vp_tree.query(&target, Query::k_nearest_neighbors(10).sorted()));
+
+            let sum = vp_tree.fold_k_nearest(&target, 10, 0.0, |acc, _, distance| acc + distance);
+            let expected: f64 = baseline_linear_search(&points, &target, 10).iter()
+                .map(|p| p.distance(&target))
+                .sum();
+            assert!((sum - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_into_parts() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..2000)
+            .map(|_| TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 })
+            .collect();
+        let mut vp_tree = VpTree::new(points.clone());
+        vp_tree.insert(TestPoint { x: 500.0, y: 500.0 });
+
+        let (items, thresholds) = vp_tree.clone().into_parts();
+        assert_eq!(items.len(), points.len() + 1);
+        assert_eq!(thresholds.len(), points.len());
+
+        let restored = VpTree::from_parts(items, thresholds);
+        assert_eq!(restored.items(), vp_tree.items());
+        assert_eq!(restored.depth(), vp_tree.depth());
+
+        for _ in 0..20 {
+            let target = TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 };
+            assert_eq!(
+                restored.query(&target, Query::k_nearest_neighbors(10).sorted()),
+                vp_tree.query(&target, Query::k_nearest_neighbors(10).sorted()),
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "distance returned a negative value")]
+    fn test_negative_distance() {
+        #[derive(Debug)]
+        struct TestPoint {
+            value: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                self.value - other.value
+            }
+        }
+
+        let points = (0..100).map(|i| TestPoint { value: i as f64 }).collect();
+        let vp_tree = VpTree::new(points);
+        vp_tree.query(&TestPoint { value: -1.0 }, Query::k_nearest_neighbors(5));
+    }
+
+    #[test]
+    fn test_into_sorted_by_distance() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..200)
+            .map(|_| TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 })
+            .collect();
+        let target = TestPoint { x: 500.0, y: 500.0 };
+
+        let mut expected = points.clone();
+        expected.sort_by(|a, b| a.distance(&target).partial_cmp(&b.distance(&target)).unwrap());
+
+        let vp_tree = VpTree::new(points);
+        assert_eq!(vp_tree.into_sorted_by_distance(&target), expected);
+    }
+
+    #[test]
+    fn test_ordered_ties() {
+        use vp_tree::{OrdKey, VpTreeOrdered};
+
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            priority: u32,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                (self.x - other.x).abs()
+            }
+        }
+        impl OrdKey for TestPoint {
+            type Key = u32;
+
+            fn ord_key(&self) -> u32 {
+                self.priority
+            }
+        }
+
+        let mut points: Vec<TestPoint> = (0..100)
+            .map(|i| TestPoint { x: if i % 2 == 0 { 10.0 } else { -10.0 }, priority: i })
+            .collect();
+        points.extend((0..1000).map(|i| TestPoint { x: 20.0 + fastrand::f64() * 100.0, priority: 1000 + i }));
+        fastrand::shuffle(&mut points);
+
+        let vp_tree = VpTreeOrdered::new(points);
+        let target = TestPoint { x: 0.0, priority: 0 };
+
+        let result = vp_tree.query(&target, Query::k_nearest_neighbors(10).sorted());
+        let priorities = result.iter().map(|p| p.priority).collect::<Vec<_>>();
+        assert_eq!(priorities, (90..100).rev().collect::<Vec<_>>());
+
+        let mut unsorted = vp_tree.query(&target, Query::k_nearest_neighbors(10))
+            .iter()
+            .map(|p| p.priority)
+            .collect::<Vec<_>>();
+        unsorted.sort_unstable_by(|a, b| b.cmp(a));
+        assert_eq!(unsorted, priorities);
+    }
+
+    #[test]
+    #[cfg(feature = "geo")]
+    fn test_geo_point() {
+        use vp_tree::metrics::GeoPoint;
+
+        let london = GeoPoint { lat: 51.5074, lon: -0.1278 };
+        let paris = GeoPoint { lat: 48.8566, lon: 2.3522 };
+        let new_york = GeoPoint { lat: 40.7128, lon: -74.0060 };
+        let los_angeles = GeoPoint { lat: 34.0522, lon: -118.2437 };
+        let sydney = GeoPoint { lat: -33.8688, lon: 151.2093 };
+        let tokyo = GeoPoint { lat: 35.6762, lon: 139.6503 };
+
+        let pairs = [
+            (london, paris, 343_500.0),
+            (new_york, los_angeles, 3_936_000.0),
+            (sydney, tokyo, 7_826_000.0),
+        ];
+        for (a, b, expected) in pairs {
+            assert!((a.distance(&b) - expected).abs() < expected * 0.005);
+            assert_eq!(a.distance(&b), b.distance(&a));
+        }
+
+        let points: Vec<GeoPoint> = (0..2000)
+            .map(|_| GeoPoint { lat: fastrand::f64() * 180.0 - 90.0, lon: fastrand::f64() * 360.0 - 180.0 })
+            .collect();
+        let vp_tree = VpTree::new(points.clone());
+        for _ in 0..20 {
+            let target = GeoPoint { lat: fastrand::f64() * 180.0 - 90.0, lon: fastrand::f64() * 360.0 - 180.0 };
+            assert_eq!(
+                vp_tree.query(&target, Query::k_nearest_neighbors(10).sorted()),
+                baseline_linear_search(&points, &target, 10),
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "instrument")]
+    fn test_distance_call_count() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            value: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                (self.value - other.value).abs()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..7).map(|i| TestPoint { value: i as f64 }).collect();
+        let vp_tree = VpTree::new(points);
+        assert_eq!(vp_tree.distance_call_count(), 0);
+
+        let target = TestPoint { value: 3.5 };
+        vp_tree.query(&target, Query::default());
+        assert_eq!(vp_tree.distance_call_count(), 7);
+
+        vp_tree.query(&target, Query::neighbors_within_radius(100.0));
+        assert_eq!(vp_tree.distance_call_count(), 14);
+
+        vp_tree.reset_counters();
+        assert_eq!(vp_tree.distance_call_count(), 0);
+
+        let single = VpTree::new(vec![TestPoint { value: 1.0 }]);
+        single.nearest_neighbor(&target);
+        assert_eq!(single.distance_call_count(), 1);
+    }
+
+    #[test]
+    fn test_within_radius_banded() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let mut points: Vec<TestPoint> = (0..5000)
+            .map(|_| TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 })
+            .collect();
+        points.push(TestPoint { x: 510.0, y: 500.0 });
+        let vp_tree = VpTree::new(points);
+
+        let target = TestPoint { x: 500.0, y: 500.0 };
+        let band_edges = [10.0, 50.0, 200.0];
+        let bands = vp_tree.within_radius_banded(&target, &band_edges);
+        assert_eq!(bands.len(), 3);
+
+        assert!(bands[0].contains(&&TestPoint { x: 510.0, y: 500.0 }));
+        let mut lower = f64::NEG_INFINITY;
+        for (band, &edge) in bands.iter().zip(band_edges.iter()) {
+            assert!(band.iter().all(|p| lower < p.distance(&target) && p.distance(&target) <= edge));
+            lower = edge;
+        }
+
+        let mut flattened = bands.into_iter().flatten().collect::<Vec<_>>();
+        let mut expected = vp_tree.query(&target, Query::neighbors_within_radius(200.0));
+        let key = |p: &&TestPoint| (p.x.to_bits(), p.y.to_bits());
+        flattened.sort_by_key(key);
+        expected.sort_by_key(key);
+        assert_eq!(flattened, expected);
+    }
+
+    #[test]
+    fn test_within_radii() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let mut points: Vec<TestPoint> = (0..5000)
+            .map(|_| TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 })
+            .collect();
+        points.push(TestPoint { x: 510.0, y: 500.0 });
+        let vp_tree = VpTree::new(points);
+
+        let target = TestPoint { x: 500.0, y: 500.0 };
+        let radii = [50.0, 10.0, 200.0, 50.0];
+        let results = vp_tree.within_radii(&target, &radii);
+        assert_eq!(results.len(), 4);
+
+        let key = |p: &&TestPoint| (p.x.to_bits(), p.y.to_bits());
+        for (mut result, &radius) in results.into_iter().zip(radii.iter()) {
+            let mut expected = vp_tree.query(&target, Query::neighbors_within_radius(radius));
+            result.sort_by_key(key);
+            expected.sort_by_key(key);
+            assert_eq!(result, expected);
+        }
+
+        assert!(vp_tree.within_radii(&target, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_representatives_by_distance() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..5000)
+            .map(|_| TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 })
+            .collect();
+        let vp_tree = VpTree::new(points.clone());
+
+        let target = TestPoint { x: 500.0, y: 500.0 };
+        let min_gaps = [1.0, 10.0, 50.0, 200.0];
+        let representatives = vp_tree.representatives_by_distance(&target, &min_gaps);
+        assert_eq!(representatives.len(), 5);
+        assert_eq!(representatives[0], vp_tree.nearest_neighbor(&target).unwrap());
+
+        let mut by_distance = points.clone();
+        by_distance.sort_by(|a, b| a.distance(&target).total_cmp(&b.distance(&target)));
+        for (pair, gap) in representatives.windows(2).zip(min_gaps) {
+            let (previous, current) = (pair[0].distance(&target), pair[1].distance(&target));
+            assert!(current >= previous + gap);
+            let expected = by_distance.iter().find(|p| p.distance(&target) >= previous + gap).unwrap();
+            assert_eq!(pair[1], expected);
+        }
+
+        let far = vp_tree.representatives_by_distance(&target, &[400.0, 400.0]);
+        assert_eq!(far.len(), 2);
+        assert_eq!(vp_tree.representatives_by_distance(&target, &[]).len(), 1);
+    }
+
+    #[test]
+    fn test_rebuild_subtree() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..5000)
+            .map(|_| TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 })
+            .collect();
+        let mut vp_tree = VpTree::new(points.clone());
+
+        let root = vp_tree.root_node().unwrap();
+        assert_eq!(root.subtree_len(), 5000);
+        let subtree = vp_tree.right_child(root).and_then(|node| vp_tree.left_child(node)).unwrap();
+        let range = subtree.index()..subtree.index() + subtree.subtree_len();
+
+        // Reverse the items of the subtree, breaking its structure but keeping the items below the same ancestors
+        let reversed = vp_tree.items()[range.clone()].iter().rev().cloned().collect::<Vec<_>>();
+        let mut items = vp_tree.items_mut_tracked();
+        for (index, item) in range.zip(reversed) {
+            *items.get_mut(index).unwrap() = item;
+        }
+        vp_tree.rebuild_subtree(subtree);
+        assert!(vp_tree.revalidate());
+
+        for _ in 0..50 {
+            let target = TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 };
+            assert_eq!(
+                vp_tree.query(&target, Query::k_nearest_neighbors(10).sorted()),
+                baseline_linear_search(&points, &target, 10),
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "euclidean")]
+    fn test_euclidean_precision() {
+        use vp_tree::metrics::Euclidean;
+
+        const D: usize = 512;
+        // Integer coordinates make the squared differences exact, the exact sum fits into an f64 but not an f32 mantissa
+        let random_vector = || (0..D).map(|_| fastrand::u32(0..10_000) as f32).collect::<Vec<_>>();
+
+        let mut f32_error = 0.0;
+        let mut f64_error = 0.0;
+        for _ in 0..20 {
+            let a = random_vector();
+            let b = random_vector();
+            let exact: u64 = a.iter().zip(b.iter())
+                .map(|(&x, &y)| (x as i64 - y as i64).pow(2) as u64)
+                .sum();
+            let f32_sum: f32 = a.iter().zip(b.iter())
+                .map(|(x, y)| (x - y) * (x - y))
+                .sum();
+
+            f32_error += (f32_sum as f64 - exact as f64).abs();
+            f64_error += (Euclidean(a.clone()).distance_heuristic(&Euclidean(b.clone())) - exact as f64).abs();
+
+            let array_a: [f32; D] = a.clone().try_into().unwrap();
+            let array_b: [f32; D] = b.clone().try_into().unwrap();
+            assert_eq!(Euclidean(array_a).distance(&Euclidean(array_b)), Euclidean(a).distance(&Euclidean(b)));
+        }
+        assert_eq!(f64_error, 0.0);
+        assert!(f32_error > 0.0);
+
+        let points: Vec<Euclidean<Vec<f32>>> = (0..500).map(|_| Euclidean(random_vector())).collect();
+        let vp_tree = VpTree::new(points.clone());
+        let target = Euclidean(random_vector());
+        assert_eq!(
+            vp_tree.query(&target, Query::k_nearest_neighbors(5).sorted()),
+            baseline_linear_search(&points, &target, 5),
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "euclidean")]
+    fn test_euclidean_f64() {
+        use vp_tree::metrics::EuclideanF64;
+
+        let dimensions = 7;
+        let random_vector = || (0..dimensions).map(|_| fastrand::f64() * 100.0).collect::<Vec<_>>();
+        let points: Vec<EuclideanF64<Vec<f64>>> = (0..1000).map(|_| EuclideanF64(random_vector())).collect();
+        let vp_tree = VpTree::new(points.clone());
+
+        for _ in 0..20 {
+            let target = EuclideanF64(random_vector());
+            assert_eq!(
+                vp_tree.query(&target, Query::k_nearest_neighbors(5).sorted()),
+                baseline_linear_search(&points, &target, 5),
+            );
+        }
+
+        let a = EuclideanF64([0.0, 0.0]);
+        let b = EuclideanF64([3.0, 4.0]);
+        assert_eq!(a.distance(&b), 5.0);
+    }
+
+    #[test]
+    #[cfg(all(feature = "euclidean", debug_assertions))]
+    #[should_panic(expected = "vectors of different dimensionality")]
+    fn test_euclidean_f64_length_mismatch() {
+        use vp_tree::metrics::EuclideanF64;
+
+        let rows = vec![EuclideanF64(vec![0.0, 1.0, 2.0]), EuclideanF64(vec![1.0, 2.0]), EuclideanF64(vec![2.0, 3.0, 4.0])];
+        VpTree::new(rows);
+    }
+
+    #[test]
+    fn test_cheap_lower_bound() {
+        use std::cell::Cell;
+
+        thread_local! {
+            static DISTANCE_CALLS: Cell<usize> = const { Cell::new(0) };
+        }
+
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                DISTANCE_CALLS.with(|calls| calls.set(calls.get() + 1));
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        struct BoundedTarget(TestPoint);
+        impl Distance<TestPoint> for BoundedTarget {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                self.0.distance(other)
+            }
+
+            fn cheap_lower_bound(&self, other: &TestPoint) -> f64 {
+                (self.0.x - other.x).abs().max((self.0.y - other.y).abs())
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..5000)
+            .map(|_| TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 })
+            .collect();
+        let mut vp_tree = VpTree::new(points.clone());
+        for _ in 0..100 {
+            vp_tree.insert(TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 });
+        }
+        let all_points = vp_tree.items().to_vec();
+
+        let mut exact_calls = 0;
+        let mut bounded_calls = 0;
+        for _ in 0..50 {
+            let target = TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 };
+            let expected = baseline_linear_search(&all_points, &target, 10);
+
+            DISTANCE_CALLS.with(|calls| calls.set(0));
+            let exact = vp_tree.query(&target, Query::k_nearest_neighbors(10).sorted());
+            exact_calls += DISTANCE_CALLS.with(|calls| calls.get());
+
+            DISTANCE_CALLS.with(|calls| calls.set(0));
+            let bounded = vp_tree.query(&BoundedTarget(target.clone()), Query::k_nearest_neighbors(10).sorted());
+            bounded_calls += DISTANCE_CALLS.with(|calls| calls.get());
+
+            assert_eq!(exact, expected);
+            assert_eq!(bounded, expected);
+            assert_eq!(
+                vp_tree.query(&BoundedTarget(target.clone()), Query::neighbors_within_radius(30.0)).len(),
+                vp_tree.query(&target, Query::neighbors_within_radius(30.0)).len(),
+            );
+        }
+        assert!(bounded_calls < exact_calls);
+    }
+
+    #[test]
+    fn test_nearest_neighbor_of_item() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..2000)
+            .map(|_| TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 })
+            .collect();
+        let vp_tree = VpTree::new(points);
+
+        for index in (0..vp_tree.items().len()).step_by(37) {
+            let item = vp_tree.item(index).unwrap();
+            let expected = vp_tree.items().iter()
+                .enumerate()
+                .filter(|&(i, _)| i != index)
+                .map(|(i, other)| (i, item.distance(other)))
+                .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+                .unwrap();
+            assert_eq!(vp_tree.nearest_neighbor_of_item(index), Some(expected));
+        }
+
+        let duplicates = VpTree::new(vec![TestPoint { x: 1.0, y: 1.0 }, TestPoint { x: 1.0, y: 1.0 }, TestPoint { x: 5.0, y: 5.0 }]);
+        for index in 0..3 {
+            let (nearest, distance) = duplicates.nearest_neighbor_of_item(index).unwrap();
+            assert_ne!(nearest, index);
+            assert_eq!(distance, if duplicates.item(index).unwrap().x == 1.0 { 0.0 } else { 32.0f64.sqrt() });
+        }
+
+        assert_eq!(VpTree::new(vec![TestPoint { x: 1.0, y: 1.0 }]).nearest_neighbor_of_item(0), None);
+    }
+
+    #[test]
+    fn test_exclusive_boundary() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            value: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                (self.value - other.value).abs()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..100).map(|i| TestPoint { value: i as f64 }).collect();
+        let vp_tree = VpTree::new(points);
+        let target = TestPoint { value: 50.0 };
+
+        let inclusive = vp_tree.query(&target, Query::neighbors_within_radius(2.0).sorted());
+        assert_eq!(inclusive.len(), 5);
+        assert!(inclusive.contains(&&TestPoint { value: 52.0 }));
+
+        let exclusive = vp_tree.query(&target, Query::neighbors_within_radius(2.0).exclusive_boundary().sorted());
+        assert_eq!(exclusive.len(), 3);
+        assert_eq!(exclusive[0], &TestPoint { value: 50.0 });
+        assert!(!exclusive.contains(&&TestPoint { value: 48.0 }));
+        assert!(!exclusive.contains(&&TestPoint { value: 52.0 }));
+
+        let both = vp_tree.query(&target, Query::neighbors_within_radius(2.0).exclusive_boundary().exclusive());
+        assert_eq!(both.len(), 2);
+
+        let knn = vp_tree.query(&target, Query::k_nearest_neighbors_within_radius(10, 1.0).exclusive_boundary());
+        assert_eq!(knn, vec![&TestPoint { value: 50.0 }]);
+    }
+
+    #[test]
+    fn test_sample_within_radius() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..10_000)
+            .map(|_| TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 })
+            .collect();
+        let vp_tree = VpTree::new(points);
+        let target = TestPoint { x: 500.0, y: 500.0 };
+
+        let sample = vp_tree.sample_within_radius(&target, 200.0, 20, 42);
+        assert_eq!(sample.len(), 20);
+        assert!(sample.iter().all(|p| p.distance(&target) <= 200.0));
+        assert_eq!(sample, vp_tree.sample_within_radius(&target, 200.0, 20, 42));
+        assert_ne!(sample, vp_tree.sample_within_radius(&target, 200.0, 20, 43));
+
+        let in_radius = vp_tree.query(&target, Query::neighbors_within_radius(10.0));
+        let all = vp_tree.sample_within_radius(&target, 10.0, in_radius.len() + 5, 42);
+        assert_eq!(all.len(), in_radius.len());
+
+        assert!(vp_tree.sample_within_radius(&target, 200.0, 0, 42).is_empty());
+    }
+
+    #[test]
+    fn test_rebuild_items_only() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+            frame: u32,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..2000)
+            .map(|_| TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0, frame: 0 })
+            .collect();
+        let mut vp_tree = VpTree::new(points);
+
+        let updated = vp_tree.items().iter()
+            .map(|p| TestPoint { frame: 1, ..p.clone() })
+            .collect::<Vec<_>>();
+        vp_tree.rebuild_items_only(updated.clone());
+
+        for _ in 0..20 {
+            let target = TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0, frame: 0 };
+            let result = vp_tree.query(&target, Query::k_nearest_neighbors(10).sorted());
+            assert!(result.iter().all(|p| p.frame == 1));
+            assert_eq!(result, baseline_linear_search(&updated, &target, 10));
+        }
+    }
+
+    #[test]
+    fn test_worst_case_probe() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        // Points on a ring, a target in the center is equidistant to all of them and can not prune
+        let points: Vec<TestPoint> = (0..2000)
+            .map(|_| {
+                let angle = fastrand::f64() * std::f64::consts::TAU;
+                let radius = 100.0 + fastrand::f64();
+                TestPoint { x: angle.cos() * radius, y: angle.sin() * radius }
+            })
+            .collect();
+        let vp_tree = VpTree::new(points.clone());
+
+        let mut candidates: Vec<TestPoint> = (0..20)
+            .map(|_| points[fastrand::usize(..points.len())].clone())
+            .collect();
+        candidates.insert(7, TestPoint { x: 0.0, y: 0.0 });
+
+        let (results, stats) = vp_tree.query_with_stats(&candidates[0], Query::k_nearest_neighbors(5).sorted());
+        assert_eq!(results, vp_tree.query(&candidates[0], Query::k_nearest_neighbors(5).sorted()));
+        assert!(stats.nodes_visited >= 5 && stats.nodes_visited < points.len());
+
+        assert_eq!(stats.fraction_of_tree_visited, stats.nodes_visited as f64 / points.len() as f64);
+
+        let (worst, stats) = vp_tree.worst_case_probe(&candidates, Query::k_nearest_neighbors(5));
+        assert_eq!(worst, 7);
+        assert!(stats.nodes_visited > points.len() / 2);
+        assert!(stats.fraction_of_tree_visited > 0.5 && stats.fraction_of_tree_visited <= 1.0);
+    }
+
+    #[test]
+    fn test_equidistant_items_balanced() {
+        use vp_tree::NodeId;
+
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        // A center point with all other points on a circle around it, every split of the center ties with the threshold
+        let mut points: Vec<TestPoint> = (0..1023)
+            .map(|i| {
+                let angle = i as f64 / 1023.0 * std::f64::consts::TAU;
+                TestPoint { x: angle.cos() * 10.0, y: angle.sin() * 10.0 }
+            })
+            .collect();
+        points.push(TestPoint { x: 0.0, y: 0.0 });
+        let vp_tree = VpTree::new(points.clone());
+        assert_eq!(vp_tree.depth(), vp_tree.expected_depth());
+
+        fn assert_balanced<T: Distance<T>>(vp_tree: &VpTree<T>, node: NodeId) {
+            let left = vp_tree.left_child(node).map_or(0, |child| child.subtree_len());
+            let right = vp_tree.right_child(node).map_or(0, |child| child.subtree_len());
+            assert!(left.abs_diff(right) <= 1);
+            for child in [vp_tree.left_child(node), vp_tree.right_child(node)].into_iter().flatten() {
+                assert_balanced(vp_tree, child);
+            }
+        }
+        assert_balanced(&vp_tree, vp_tree.root_node().unwrap());
+
+        let target = TestPoint { x: 0.0, y: 0.0 };
+        assert_eq!(vp_tree.query(&target, Query::neighbors_within_radius(10.0 + 1e-9)).len(), 1024);
+        assert_eq!(
+            vp_tree.query(&TestPoint { x: 9.0, y: 0.5 }, Query::k_nearest_neighbors(10).sorted()),
+            baseline_linear_search(&points, &TestPoint { x: 9.0, y: 0.5 }, 10),
+        );
+    }
+
+    #[test]
+    fn test_query_cloned() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..2000)
+            .map(|_| TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 })
+            .collect();
+        let vp_tree = VpTree::new(points);
+
+        for _ in 0..20 {
+            let target = TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 };
+            let cloned = vp_tree.query_cloned(&target, Query::k_nearest_neighbors(10).sorted());
+            let referenced = vp_tree.query(&target, Query::k_nearest_neighbors(10).sorted());
+            assert_eq!(cloned.iter().collect::<Vec<_>>(), referenced);
+        }
+    }
+
+    #[test]
+    fn test_knn_graph() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..500)
+            .map(|_| TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 })
+            .collect();
+        let vp_tree = VpTree::new(points);
+        let items = vp_tree.items();
+
+        let brute_force = (0..items.len())
+            .map(|i| {
+                let mut others = (0..items.len()).filter(|&j| j != i).collect::<Vec<_>>();
+                others.sort_by(|&a, &b| items[i].distance(&items[a]).partial_cmp(&items[i].distance(&items[b])).unwrap());
+                others.truncate(5);
+                others
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(vp_tree.knn_graph(5), brute_force);
+        assert_eq!(vp_tree.knn_graph_parallel(5, 4), brute_force);
+        assert_eq!(vp_tree.knn_graph_parallel(5, 1000), brute_force);
+    }
+
+    #[test]
+    fn test_estimate_intrinsic_dim() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            cords: [f64; 4],
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                self.cords.iter().zip(other.cords.iter())
+                    .map(|(a, b)| (a - b) * (a - b))
+                    .sum::<f64>()
+                    .sqrt()
+            }
+        }
+
+        // A 2-D plane embedded in 4 dimensions
+        let plane: Vec<TestPoint> = (0..20_000)
+            .map(|_| {
+                let (u, v) = (fastrand::f64() * 1000.0, fastrand::f64() * 1000.0);
+                TestPoint { cords: [u, v, u + v, u - 2.0 * v] }
+            })
+            .collect();
+        let estimate = VpTree::new(plane).estimate_intrinsic_dim(2000);
+        assert!((estimate - 2.0).abs() < 0.25, "estimate: {estimate}");
+
+        let volume: Vec<TestPoint> = (0..20_000)
+            .map(|_| TestPoint { cords: [(); 4].map(|_| fastrand::f64() * 1000.0) })
+            .collect();
+        let estimate = VpTree::new(volume).estimate_intrinsic_dim(2000);
+        assert!((estimate - 4.0).abs() < 0.6, "estimate: {estimate}");
+
+        assert!(VpTree::new(vec![TestPoint { cords: [0.0; 4] }]).estimate_intrinsic_dim(10).is_nan());
+    }
+
+    #[test]
+    fn test_nearest_neighbor_index() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..2000)
+            .map(|_| TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 })
+            .collect();
+        let vp_tree = VpTree::new(points);
+
+        for _ in 0..20 {
+            let target = TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 };
+            let index = vp_tree.nearest_neighbor_index(&target).unwrap();
+            assert_eq!(vp_tree.item(index), vp_tree.nearest_neighbor(&target));
+        }
+
+        for index in (0..vp_tree.items().len()).step_by(101) {
+            let target = vp_tree.item(index).unwrap().clone();
+            assert_eq!(vp_tree.nearest_neighbor_index(&target), Some(index));
+
+            let exclusive = vp_tree.nearest_neighbor_index_exclusive(&target).unwrap();
+            assert_ne!(exclusive, index);
+            assert_eq!(vp_tree.item(exclusive), vp_tree.nearest_neighbor_exclusive(&target));
+        }
+
+        assert_eq!(VpTree::<TestPoint>::new(Vec::new()).nearest_neighbor_index(&TestPoint { x: 0.0, y: 0.0 }), None);
+    }
+
+    #[test]
+    fn test_nearest_neighbor_exact_match() {
+        use std::cell::Cell;
+
+        thread_local! {
+            static DISTANCE_CALLS: Cell<usize> = const { Cell::new(0) };
+        }
+
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                DISTANCE_CALLS.with(|calls| calls.set(calls.get() + 1));
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..10_000)
+            .map(|_| TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 })
+            .collect();
+        let vp_tree = VpTree::new(points.clone());
+
+        let mut inclusive_calls = 0;
+        let mut exclusive_calls = 0;
+        for target in points.iter().step_by(97) {
+            DISTANCE_CALLS.with(|calls| calls.set(0));
+            assert_eq!(vp_tree.nearest_neighbor(target), Some(target));
+            inclusive_calls += DISTANCE_CALLS.with(|calls| calls.get());
+
+            // The exclusive search ignores the exact match and has to finish the traversal
+            DISTANCE_CALLS.with(|calls| calls.set(0));
+            let exclusive = vp_tree.nearest_neighbor_exclusive(target).unwrap();
+            exclusive_calls += DISTANCE_CALLS.with(|calls| calls.get());
+
+            assert_ne!(exclusive, target);
+            assert_eq!(exclusive, baseline_linear_search(&points, target, 2)[1]);
+        }
+        assert!(inclusive_calls < exclusive_calls);
+    }
+
+    #[test]
+    fn test_nearest_by_cmp() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..2000)
+            .map(|_| TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 })
+            .collect();
+        let vp_tree = VpTree::new(points);
+
+        // Rank by the larger coordinate difference, which only needs comparisons
+        let cmp = |reference: &TestPoint, a: &TestPoint, b: &TestPoint| {
+            let key = |p: &TestPoint| (reference.x - p.x).abs().max((reference.y - p.y).abs());
+            key(a).total_cmp(&key(b))
+        };
+
+        for _ in 0..20 {
+            let reference = TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 };
+            let expected = vp_tree.items().iter().min_by(|a, b| cmp(&reference, a, b));
+            assert_eq!(vp_tree.nearest_by_cmp(&reference, cmp), expected);
+        }
+
+        assert_eq!(VpTree::<TestPoint>::new(Vec::new()).nearest_by_cmp(&TestPoint { x: 0.0, y: 0.0 }, cmp), None);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "distance_heuristic orders items differently than distance")]
+    fn test_non_monotonic_heuristic() {
+        #[derive(Debug)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+
+            fn distance_heuristic(&self, other: &TestPoint) -> f64 {
+                (self.x - other.x).abs() + (self.y - other.y).abs()
+            }
+        }
+
+        let points = (0..1000)
+            .map(|_| TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 })
+            .collect();
+        VpTree::new(points);
+    }
+
+    #[test]
+    fn test_nearest_along_path() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..10_000)
+            .map(|_| TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 })
+            .collect();
+        let vp_tree = VpTree::new(points);
+
+        let path: Vec<TestPoint> = (0..500)
+            .map(|i| {
+                let t = i as f64 / 500.0 * std::f64::consts::TAU;
+                TestPoint { x: 500.0 + t.cos() * 300.0, y: 500.0 + (2.0 * t).sin() * 200.0 }
+            })
+            .collect();
+
+        let along_path = vp_tree.nearest_along_path(&path);
+        let independent = path.iter().map(|target| vp_tree.nearest_neighbor(target)).collect::<Vec<_>>();
+        assert_eq!(along_path, independent);
+
+        let on_items = vp_tree.items()[..10].to_vec();
+        assert_eq!(vp_tree.nearest_along_path(&on_items), on_items.iter().map(Some).collect::<Vec<_>>());
+
+        assert_eq!(VpTree::<TestPoint>::new(Vec::new()).nearest_along_path(&path[..2]), vec![None, None]);
+    }
+
+    #[test]
+    fn test_default() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            value: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                (self.value - other.value).abs()
+            }
+        }
+
+        let vp_tree = VpTree::<TestPoint>::default();
+        let target = TestPoint { value: 1.0 };
+        assert!(vp_tree.items().is_empty());
+        assert!(vp_tree.query(&target, Query::default()).is_empty());
+        assert_eq!(vp_tree.nearest_neighbor(&target), None);
+
+        let mut vp_tree = VpTree::new(vec![TestPoint { value: 1.0 }, TestPoint { value: 2.0 }]);
+        let taken = std::mem::take(&mut vp_tree);
+        assert_eq!(taken.items().len(), 2);
+        assert!(vp_tree.items().is_empty());
+    }
+
+    #[test]
+    fn test_from_iter_auto() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        for len in [0, 1, 100, 100_000] {
+            let points: Vec<TestPoint> = (0..len)
+                .map(|_| TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 })
+                .collect();
+            let vp_tree = VpTree::from_iter_auto(points.iter().cloned());
+            assert_eq!(vp_tree.items().len(), len);
+            assert_eq!(vp_tree.depth(), vp_tree.expected_depth());
+
+            for _ in 0..10 {
+                let target = TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 };
+                assert_eq!(
+                    vp_tree.query(&target, Query::k_nearest_neighbors(5).sorted()),
+                    baseline_linear_search(&points, &target, 5),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_append() {
+        use vp_tree::RebuildPolicy;
+
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let random_points = |len: usize| (0..len)
+            .map(|_| TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 })
+            .collect::<Vec<_>>();
+
+        let mut points = random_points(1000);
+        let mut vp_tree = VpTree::new(points.clone());
+        let built_depth = vp_tree.depth();
+        for _ in 0..5 {
+            let burst = random_points(50);
+            points.extend(burst.iter().cloned());
+            vp_tree.append(burst);
+        }
+        assert_eq!(vp_tree.items().len(), 1250);
+        assert_eq!(vp_tree.depth(), built_depth + 250);
+
+        let target = TestPoint { x: 500.0, y: 500.0 };
+        assert_eq!(vp_tree.query(&target, Query::default()).len(), 1250);
+        assert_eq!(vp_tree.query(&target, Query::k_nearest_neighbors(10).sorted()), baseline_linear_search(&points, &target, 10));
+
+        // A single rebuild for a whole burst
+        vp_tree.set_rebuild_policy(RebuildPolicy::AfterMutations(100));
+        assert_eq!(vp_tree.depth(), vp_tree.expected_depth());
+        let built_depth = vp_tree.depth();
+        let burst = random_points(80);
+        points.extend(burst.iter().cloned());
+        vp_tree.append(burst);
+        assert_eq!(vp_tree.depth(), built_depth + 80);
+        let burst = random_points(80);
+        points.extend(burst.iter().cloned());
+        vp_tree.append(burst);
+        assert_eq!(vp_tree.depth(), vp_tree.expected_depth());
+        assert_eq!(vp_tree.query(&target, Query::k_nearest_neighbors(10).sorted()), baseline_linear_search(&points, &target, 10));
+
+        // The first search after a burst indexes the appended items, items inserted afterwards are compared linearly
+        let mut vp_tree = VpTree::new(random_points(1000));
+        let mut points = vp_tree.items().to_vec();
+        for _ in 0..10 {
+            let burst = random_points(500);
+            points.extend(burst.iter().cloned());
+            vp_tree.append(burst);
+        }
+        let inserted = TestPoint { x: 500.5, y: 500.5 };
+        vp_tree.insert(inserted.clone());
+        points.push(inserted);
+        for _ in 0..20 {
+            let target = TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 };
+            assert_eq!(vp_tree.query(&target, Query::k_nearest_neighbors(10).sorted()), baseline_linear_search(&points, &target, 10));
+            assert_eq!(vp_tree.query(&target, Query::neighbors_within_radius(50.0)).len(), points.iter().filter(|p| p.distance(&target) <= 50.0).count());
+        }
+        assert_eq!(vp_tree.query(&target, Query::k_nearest_neighbors(1)), vec![&points[points.len() - 1]]);
+
+        #[cfg(feature = "instrument")]
+        {
+            vp_tree.reset_counters();
+            vp_tree.query(&target, Query::k_nearest_neighbors(10));
+            assert!(vp_tree.distance_call_count() < 5000, "appended items are pruned instead of compared linearly");
+        }
+
+        // Appended items are indexed again after they were modified
+        let mut tracked = vp_tree.items_mut_tracked();
+        for (index, point) in points.iter_mut().enumerate().skip(1000) {
+            point.x *= 0.5;
+            tracked.get_mut(index).unwrap().x = point.x;
+        }
+        assert_eq!(vp_tree.query(&target, Query::k_nearest_neighbors(10).sorted()), baseline_linear_search(&points, &target, 10));
+    }
+
+    #[test]
+    #[cfg(feature = "periodic")]
+    fn test_periodic_euclidean() {
+        use vp_tree::metrics::PeriodicEuclidean;
+
+        let box_size = [10.0, 20.0, 5.0];
+        let minimum_image = |a: &[f64; 3], b: &[f64; 3]| {
+            let mut best = f64::INFINITY;
+            for sx in -1..=1 {
+                for sy in -1..=1 {
+                    for sz in -1..=1 {
+                        let shift = [sx as f64 * box_size[0], sy as f64 * box_size[1], sz as f64 * box_size[2]];
+                        let squared: f64 = (0..3).map(|i| (a[i] - b[i] + shift[i]).powi(2)).sum();
+                        best = best.min(squared.sqrt());
+                    }
+                }
+            }
+            best
+        };
+        let random_point = || PeriodicEuclidean { coords: std::array::from_fn(|i| fastrand::f64() * box_size[i]), box_size };
+
+        let points: Vec<PeriodicEuclidean<3>> = (0..2000).map(|_| random_point()).collect();
+        for pair in points.windows(2).take(200) {
+            assert!((pair[0].distance(&pair[1]) - minimum_image(&pair[0].coords, &pair[1].coords)).abs() < 1e-9);
+        }
+
+        let vp_tree = VpTree::new(points.clone());
+        for _ in 0..50 {
+            let target = random_point();
+            assert_eq!(
+                vp_tree.query(&target, Query::k_nearest_neighbors(10).sorted()),
+                baseline_linear_search(&points, &target, 10),
+            );
+        }
+    }
+
+    #[test]
+    fn test_arc_items() {
+        use std::sync::Arc;
+
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<Arc<TestPoint>> = (0..2000)
+            .map(|_| Arc::new(TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 }))
+            .collect();
+        let vp_tree = VpTree::new(points.clone());
+
+        for _ in 0..20 {
+            let target = TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 };
+            let expected = baseline_linear_search(&points, &target, 10);
+
+            assert_eq!(vp_tree.query(&target, Query::k_nearest_neighbors(10).sorted()), expected);
+            assert_eq!(vp_tree.query(&Arc::new(target.clone()), Query::k_nearest_neighbors(10).sorted()), expected);
+
+            let shared: Vec<Arc<TestPoint>> = vp_tree.query_cloned(&target, Query::k_nearest_neighbors(10).sorted());
+            assert!(shared.iter().zip(expected).all(|(a, b)| Arc::ptr_eq(a, b)));
+        }
+    }
+
+    #[test]
+    fn test_box_items() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<Box<TestPoint>> = (0..2000)
+            .map(|_| Box::new(TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 }))
+            .collect();
+        let vp_tree = VpTree::new(points.clone());
+
+        for _ in 0..20 {
+            let target = TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 };
+            let expected = baseline_linear_search(&points, &target, 10);
+
+            assert_eq!(vp_tree.query(&target, Query::k_nearest_neighbors(10).sorted()), expected);
+            assert_eq!(vp_tree.query(&Box::new(target.clone()), Query::k_nearest_neighbors(10).sorted()), expected);
+            assert_eq!(vp_tree.nearest_neighbor(&target), Some(expected[0]));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "euclidean")]
+    fn test_scaled_euclidean() {
+        use vp_tree::metrics::ScaledEuclidean;
+
+        let weights = [4.0, 1.0, 0.0];
+        let random_point = || ScaledEuclidean::new([(); 3].map(|_| fastrand::f64() * 1000.0), weights);
+
+        let points: Vec<ScaledEuclidean<3>> = (0..2000).map(|_| random_point()).collect();
+        assert_eq!(points[0].weights(), weights);
+        for pair in points.windows(2).take(100) {
+            let [a, b] = [&pair[0].coords, &pair[1].coords];
+            let expected = ((2.0 * (a[0] - b[0])).powi(2) + (a[1] - b[1]).powi(2)).sqrt();
+            assert!((pair[0].distance(&pair[1]) - expected).abs() < 1e-9);
+        }
+
+        // The third axis has weight zero, the tree behaves like a 2-D tree
+        let vp_tree = VpTree::new(points.clone());
+        for _ in 0..20 {
+            let target = random_point();
+            let moved = ScaledEuclidean::new([target.coords[0], target.coords[1], -1e6], weights);
+            let result = vp_tree.query(&target, Query::k_nearest_neighbors(10).sorted());
+            assert_eq!(result, baseline_linear_search(&points, &target, 10));
+            assert_eq!(vp_tree.query(&moved, Query::k_nearest_neighbors(10).sorted()), result);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "euclidean")]
+    #[should_panic(expected = "weights must be non-negative")]
+    fn test_scaled_euclidean_negative_weight() {
+        use vp_tree::metrics::ScaledEuclidean;
+
+        ScaledEuclidean::new([0.0, 0.0], [1.0, -1.0]);
+    }
+
+    #[test]
+    fn test_query_unique_by() {
+        use std::collections::HashSet;
+
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+            sensor: u32,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        // Every sensor has several readings clustered close to each other
+        let points: Vec<TestPoint> = (0..500)
+            .flat_map(|sensor| {
+                let (x, y) = (fastrand::f64() * 1000.0, fastrand::f64() * 1000.0);
+                (0..8).map(move |_| TestPoint { x: x + fastrand::f64(), y: y + fastrand::f64(), sensor })
+            })
+            .collect();
+        let vp_tree = VpTree::new(points.clone());
+
+        for _ in 0..20 {
+            let target = TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0, sensor: 0 };
+            let result = vp_tree.query_unique_by(&target, Query::k_nearest_neighbors(10), |p| p.sensor);
+
+            let mut sensors = HashSet::new();
+            let expected = baseline_linear_search(&points, &target, points.len())
+                .into_iter()
+                .filter(|p| sensors.insert(p.sensor))
+                .take(10)
+                .collect::<Vec<_>>();
+            assert_eq!(result, expected);
+
+            let in_radius = vp_tree.query_unique_by(&target, Query::neighbors_within_radius(100.0), |p| p.sensor);
+            let sensors = in_radius.iter().map(|p| p.sensor).collect::<HashSet<_>>();
+            assert_eq!(sensors.len(), in_radius.len());
+        }
+    }
+
+    #[test]
+    fn test_in_order_tree() {
+        use vp_tree::VpTreeInOrder;
+
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..2000)
+            .map(|_| TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 })
+            .collect();
+        let vp_tree = VpTreeInOrder::new(points.clone());
+        assert_eq!(vp_tree.items(), &points[..]);
+
+        let parallel = VpTreeInOrder::new_parallel(points.clone(), 4);
+        assert_eq!(parallel.items(), &points[..]);
+
+        for _ in 0..50 {
+            let target = TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 };
+            let expected = baseline_linear_search(&points, &target, 10);
+
+            assert_eq!(vp_tree.query(&target, Query::k_nearest_neighbors(10).sorted()), expected);
+            assert_eq!(parallel.query(&target, Query::k_nearest_neighbors(10).sorted()), expected);
+
+            let indices = vp_tree.query_indices(&target, Query::k_nearest_neighbors(10).sorted());
+            assert_eq!(indices.iter().map(|&i| &points[i]).collect::<Vec<_>>(), expected);
+
+            let nearest = vp_tree.nearest_neighbor_index(&target).unwrap();
+            assert_eq!(&points[nearest], expected[0]);
+        }
+    }
+
+    #[test]
+    fn test_insert_if_spaced() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let min_distance = 2.0;
+        let mut vp_tree = VpTree::new(Vec::new());
+        vp_tree.set_rebuild_policy(vp_tree::RebuildPolicy::AfterMutations(64));
+
+        let mut rejected = 0;
+        for _ in 0..5000 {
+            let point = TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 };
+            match vp_tree.insert_if_spaced(point.clone(), min_distance) {
+                Ok(()) => assert!(vp_tree.items().contains(&point)),
+                Err(returned) => {
+                    assert_eq!(returned, point);
+                    rejected += 1;
+                }
+            }
+        }
+        assert!(rejected > 0);
+        assert_eq!(vp_tree.items().len() + rejected, 5000);
+
+        let items = vp_tree.items();
+        for (i, a) in items.iter().enumerate() {
+            for b in &items[i + 1..] {
+                assert!(a.distance(b) > min_distance);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "inline-knn")]
+    fn test_inline_knn_matches_heap() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..3000)
+            .map(|_| TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 })
+            .collect();
+        let vp_tree = VpTree::new(points.clone());
+
+        for _ in 0..20 {
+            let target = points[fastrand::usize(..points.len())].clone();
+            // Sizes around the inline capacity use both search paths
+            for k in 1..=20 {
+                let expected = baseline_linear_search(&points, &target, k);
+                assert_eq!(vp_tree.query(&target, Query::k_nearest_neighbors(k).sorted()), expected);
+
+                let result = vp_tree.query(&target, Query::k_nearest_neighbors_within_radius(k, 3.0).exclusive().sorted());
+                let expected = baseline_linear_search(&points, &target, k + 1).into_iter().filter(|p| **p != target).take_while(|p| p.distance(&target) <= 3.0).take(k).collect::<Vec<_>>();
+                assert_eq!(result, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_capacity() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                (self.x - other.x).abs()
+            }
+        }
+
+        let mut vp_tree = VpTree::with_capacity(100);
+        let (items, thresholds) = vp_tree.capacity();
+        assert!(items >= 100);
+        assert!(thresholds >= 100);
+        assert!(vp_tree.items().is_empty());
+
+        for i in 0..100 {
+            vp_tree.insert(TestPoint { x: i as f64 });
+        }
+        assert_eq!(vp_tree.capacity().0, items);
+
+        vp_tree.rebuild();
+        assert_eq!(vp_tree.items().len(), 100);
+        assert!(vp_tree.capacity().1 >= 100);
+        assert_eq!(vp_tree.nearest_neighbor(&TestPoint { x: 41.8 }), Some(&TestPoint { x: 42.0 }));
+    }
+
+    #[test]
+    fn test_kth_distance() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..1000)
+            .map(|_| TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 })
+            .collect();
+        let vp_tree = VpTree::new(points.clone());
+
+        for point in points.iter().take(100) {
+            for k in [1, 4, 10] {
+                let nearest = vp_tree.query(point, Query::k_nearest_neighbors(k).sorted());
+                assert_eq!(vp_tree.kth_distance(point, k), Some(nearest[k - 1].distance(point)));
+            }
+        }
+        assert_eq!(vp_tree.kth_distance(&points[0], 1), Some(0.0));
+        assert_eq!(vp_tree.kth_distance(&points[0], 1001), None);
+    }
+
+    #[test]
+    fn test_new_index_in_place() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        struct Owner {
+            points: Vec<TestPoint>,
+        }
+
+        let points: Vec<TestPoint> = (0..2000)
+            .map(|_| TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 })
+            .collect();
+        let mut owner = Owner { points: points.clone() };
+
+        let vp_tree = VpTree::new_index_in_place(&mut owner.points);
+        let addresses = vp_tree.items().iter().map(|item| *item as *const TestPoint).collect::<Vec<_>>();
+
+        for _ in 0..50 {
+            let target = TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 };
+            let expected = baseline_linear_search(&points, &target, 10);
+            let result = vp_tree.query(&target, Query::k_nearest_neighbors(10).sorted());
+            assert_eq!(result.into_iter().copied().collect::<Vec<_>>(), expected);
+        }
+
+        drop(vp_tree);
+        assert!(owner.points.iter().zip(addresses).all(|(item, address)| std::ptr::eq(item, address)));
+        assert!(points.iter().all(|p| owner.points.contains(p)));
+    }
+
+    #[test]
+    fn test_query_initial_bound() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..10_000)
+            .map(|_| TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 })
+            .collect();
+        let vp_tree = VpTree::new(points.clone());
+
+        let mut unbounded_visits = 0;
+        let mut bounded_visits = 0;
+        for _ in 0..100 {
+            let target = TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 };
+            let expected = baseline_linear_search(&points, &target, 5);
+            let bound = expected[4].distance(&target);
+
+            let (result, stats) = vp_tree.query_with_stats(&target, Query::k_nearest_neighbors(5).sorted());
+            assert_eq!(result, expected);
+            unbounded_visits += stats.nodes_visited;
+
+            let (result, stats) = vp_tree.query_with_stats(&target, Query::k_nearest_neighbors(5).initial_bound(bound).sorted());
+            assert_eq!(result, expected);
+            bounded_visits += stats.nodes_visited;
+        }
+        assert!(bounded_visits < unbounded_visits);
+    }
+
+    #[test]
+    fn test_distance_rank_order() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..2000)
+            .map(|_| TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 })
+            .collect();
+        let mut vp_tree = VpTree::new(points);
+        vp_tree.insert(TestPoint { x: 50.0, y: 50.0 });
+
+        for _ in 0..10 {
+            let target = TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 };
+            let order = vp_tree.distance_rank_order(&target);
+            assert_eq!(order.len(), vp_tree.items().len());
+            assert_eq!(order[0], vp_tree.nearest_neighbor_index(&target).unwrap());
+
+            let mut expected = (0..vp_tree.items().len()).collect::<Vec<_>>();
+            expected.sort_by(|&a, &b| target.distance(&vp_tree.items()[a]).total_cmp(&target.distance(&vp_tree.items()[b])));
+            assert_eq!(order, expected);
+        }
+    }
+
+    #[test]
+    fn test_parallel_build_with_more_threads_than_items() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        for len in [0, 1, 2, 100, 10_000] {
+            let points: Vec<TestPoint> = (0..len)
+                .map(|_| TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 })
+                .collect();
+            let vp_tree = VpTree::new_parallel(points.clone(), usize::MAX);
+            assert_eq!(vp_tree.items().len(), len);
+            assert_eq!(vp_tree.depth(), vp_tree.expected_depth());
+
+            for _ in 0..20 {
+                let target = TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 };
+                let expected = baseline_linear_search(&points, &target, 5);
+                assert_eq!(vp_tree.query(&target, Query::k_nearest_neighbors(5).sorted()), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_nearest_neighbor_batch_indices() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..5000)
+            .map(|_| TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 })
+            .collect();
+        let vp_tree = VpTree::new(points);
+
+        let targets: Vec<TestPoint> = (0..1000)
+            .map(|_| TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 })
+            .collect();
+
+        for threads in [1, 3, 8] {
+            let indices = vp_tree.nearest_neighbor_batch_indices(&targets, threads);
+            assert_eq!(indices.len(), targets.len());
+            for (target, index) in targets.iter().zip(indices) {
+                assert_eq!(index.map(|index| index as usize), vp_tree.nearest_neighbor_index(target));
+            }
+        }
+
+        let empty: VpTree<TestPoint> = VpTree::new(Vec::new());
+        assert_eq!(empty.nearest_neighbor_batch_indices(&targets[..3], 2), vec![None; 3]);
+        assert!(vp_tree.nearest_neighbor_batch_indices(&targets[..0], 4).is_empty());
+    }
+
+    #[test]
+    fn test_subtree_items() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..3000)
+            .map(|_| TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 })
+            .collect();
+        let vp_tree = VpTree::new(points);
+
+        let root = vp_tree.root_node().unwrap();
+        assert_eq!(vp_tree.subtree_items(root), vp_tree.items());
+
+        let mut node = root;
+        while let Some(left) = vp_tree.left_child(node) {
+            let vantage = &vp_tree.subtree_items(node)[0];
+            let threshold = vp_tree.node_threshold(node);
+            let subtree = vp_tree.subtree_items(node);
+            let left_items = vp_tree.subtree_items(left);
+            assert_eq!(left_items.len() + vp_tree.right_child(node).map_or(0, |right| vp_tree.subtree_items(right).len()) + 1, subtree.len());
+
+            // Every item of the subtree strictly inside the threshold belongs to the left subtree
+            let inside = vp_tree.query(vantage, Query::neighbors_within_radius(threshold).exclusive_boundary());
+            let inside_subtree = inside.iter().filter(|item| subtree.iter().any(|s| std::ptr::eq(s, **item))).count();
+            let inside_left = left_items.iter().filter(|item| vantage.distance(item) < threshold).count();
+            assert_eq!(inside_subtree, inside_left + 1);
+            assert!(left_items.iter().all(|item| vantage.distance(item) <= threshold));
+
+            node = left;
+        }
+    }
+
+    #[test]
+    fn test_prune_sparse() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        // Dense blob around the origin, noise points on a coarse grid far away from it and from each other
+        let blob: Vec<TestPoint> = (0..1000)
+            .map(|_| TestPoint { x: fastrand::f64() * 10.0, y: fastrand::f64() * 10.0 })
+            .collect();
+        let noise: Vec<TestPoint> = (1..10)
+            .flat_map(|i| (1..10).map(move |j| TestPoint { x: i as f64 * 100.0, y: j as f64 * 100.0 }))
+            .collect();
+
+        let mut vp_tree = VpTree::new(blob.iter().chain(&noise).cloned().collect());
+        vp_tree.prune_sparse(2.0, 5);
+
+        assert_eq!(vp_tree.items().len(), blob.len());
+        assert!(noise.iter().all(|p| !vp_tree.items().contains(p)));
+        assert!(blob.iter().all(|p| vp_tree.items().contains(p)));
+        assert_eq!(vp_tree.depth(), vp_tree.expected_depth());
+
+        let target = TestPoint { x: 5.0, y: 5.0 };
+        assert_eq!(vp_tree.query(&target, Query::k_nearest_neighbors(10).sorted()), baseline_linear_search(&blob, &target, 10));
+
+        vp_tree.prune_sparse(100.0, 0);
+        assert_eq!(vp_tree.items().len(), blob.len());
+    }
+
+    #[test]
+    fn test_sorted_refs_by_distance() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..2000)
+            .map(|_| TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 })
+            .collect();
+        let mut vp_tree = VpTree::new(points.clone());
+        vp_tree.insert(TestPoint { x: 50.0, y: 50.0 });
+        let mut all_points = points;
+        all_points.push(TestPoint { x: 50.0, y: 50.0 });
+
+        for _ in 0..10 {
+            let target = TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 };
+            assert_eq!(vp_tree.sorted_refs_by_distance(&target), baseline_linear_search(&all_points, &target, all_points.len()));
+        }
+
+        let target = TestPoint { x: 10.0, y: 10.0 };
+        let sorted = vp_tree.sorted_refs_by_distance(&target).into_iter().cloned().collect::<Vec<_>>();
+        assert_eq!(vp_tree.into_sorted_by_distance(&target), sorted);
+    }
+
+    #[test]
+    fn test_query_collapse_duplicates() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        // Every distinct point is stored up to five times
+        let distinct: Vec<TestPoint> = (0..500)
+            .map(|_| TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 })
+            .collect();
+        let points = distinct.iter()
+            .flat_map(|p| std::iter::repeat_n(p.clone(), fastrand::usize(1..=5)))
+            .collect::<Vec<_>>();
+        let vp_tree = VpTree::new(points);
+
+        for _ in 0..20 {
+            let target = TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 };
+
+            let result = vp_tree.query(&target, Query::k_nearest_neighbors(10).collapse_duplicates().sorted());
+            assert_eq!(result, baseline_linear_search(&distinct, &target, 10));
+
+            let in_radius = vp_tree.query(&target, Query::neighbors_within_radius(20.0).collapse_duplicates());
+            for (i, a) in in_radius.iter().enumerate() {
+                assert!(in_radius[i + 1..].iter().all(|b| a.distance(b) > 0.0));
+            }
+            assert_eq!(in_radius.len(), distinct.iter().filter(|p| p.distance(&target) <= 20.0).count());
+        }
+    }
+
+    #[test]
+    fn test_collapse_duplicates_in_order_and_ordered() {
+        use vp_tree::{OrdKey, VpTreeInOrder, VpTreeOrdered};
+
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            priority: u32,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                (self.x - other.x).abs()
+            }
+        }
+        impl OrdKey for TestPoint {
+            type Key = u32;
+
+            fn ord_key(&self) -> u32 {
+                self.priority
+            }
+        }
+
+        // Every position is stored three times with different priorities
+        let mut points: Vec<TestPoint> = (0..100)
+            .flat_map(|x| (0..3).map(move |priority| TestPoint { x: x as f64, priority }))
+            .collect();
+        fastrand::shuffle(&mut points);
+        let target = TestPoint { x: 0.2, priority: 0 };
+        let query = Query::k_nearest_neighbors(5).collapse_duplicates().sorted();
+
+        let in_order = VpTreeInOrder::new(points.clone());
+        let xs = in_order.query_indices(&target, &query)
+            .into_iter()
+            .map(|index| points[index].x)
+            .collect::<Vec<_>>();
+        assert_eq!(xs, vec![0.0, 1.0, 2.0, 3.0, 4.0]);
+
+        let ordered = VpTreeOrdered::new(points);
+        let result = ordered.query(&target, &query)
+            .into_iter()
+            .map(|p| (p.x, p.priority))
+            .collect::<Vec<_>>();
+        assert_eq!(result, vec![(0.0, 2), (1.0, 2), (2.0, 2), (3.0, 2), (4.0, 2)]);
+
+        let in_radius = ordered.query(&target, Query::neighbors_within_radius(10.0).collapse_duplicates());
+        assert_eq!(in_radius.len(), 11);
+        assert!(in_radius.iter().all(|p| p.priority == 2));
+    }
+
+    #[test]
+    fn test_new_index_with_map() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..10_000)
+            .map(|_| TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 })
+            .collect();
+        let original = points.clone();
+
+        let (vp_tree, map) = VpTree::new_index_with_map(&points, 4);
+        assert_eq!(points, original);
+        assert_eq!(map.len(), points.len());
+        assert!(vp_tree.items().iter().zip(&map).all(|(item, &position)| std::ptr::eq(*item, &points[position])));
+
+        for _ in 0..50 {
+            let target = TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 };
+            let expected = baseline_linear_search(&points, &target, 10);
+            let result = vp_tree.query(&target, Query::k_nearest_neighbors(10).sorted());
+            assert_eq!(result.into_iter().copied().collect::<Vec<_>>(), expected);
+
+            let nearest = vp_tree.nearest_neighbor_index(&target).unwrap();
+            assert_eq!(&points[map[nearest]], expected[0]);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "testing")]
+    fn test_assert_matches_linear() {
+        use vp_tree::testing::assert_matches_linear;
+
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..2000)
+            .map(|_| TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 })
+            .collect();
+        let vp_tree = VpTree::new(points.clone());
+
+        for k in [1, 10, 2500] {
+            let target = TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 };
+            assert_matches_linear(&vp_tree, &points, &target, k);
+        }
+
+        // A tree not matching the data has to be reported
+        let shifted = points.iter().map(|p| TestPoint { x: p.x + 0.5, y: p.y }).collect::<Vec<_>>();
+        let target = TestPoint { x: 50.0, y: 50.0 };
+        let result = std::panic::catch_unwind(|| assert_matches_linear(&vp_tree, &shifted, &target, 10));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_within_radius_sorted_with_distances() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..5000)
+            .map(|_| TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 })
+            .collect();
+        let vp_tree = VpTree::new(points.clone());
+
+        for _ in 0..50 {
+            let target = TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 };
+            let radius = fastrand::f64() * 10.0;
+            let result = vp_tree.within_radius_sorted_with_distances(&target, radius);
+
+            let expected = baseline_linear_search(&points, &target, points.len())
+                .into_iter()
+                .take_while(|p| target.distance(*p) <= radius)
+                .collect::<Vec<_>>();
+            assert_eq!(result.iter().map(|(item, _)| *item).collect::<Vec<_>>(), expected);
+            assert!(result.iter().all(|(item, distance)| target.distance(*item) == *distance));
+            assert!(result.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+        }
+    }
+
+    #[test]
+    fn test_parallel_search_of_sync_but_not_send_items() {
+        use std::{marker::PhantomData, sync::MutexGuard};
+
+        // MutexGuard is Sync but not Send, so is the point
+        #[derive(Debug, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+            _not_send: PhantomData<MutexGuard<'static, ()>>,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+        fn point(x: f64, y: f64) -> TestPoint {
+            TestPoint { x, y, _not_send: PhantomData }
+        }
+
+        let vp_tree = VpTree::new((0..2000).map(|_| point(fastrand::f64() * 100.0, fastrand::f64() * 100.0)).collect());
+        let targets = (0..200).map(|_| point(fastrand::f64() * 100.0, fastrand::f64() * 100.0)).collect::<Vec<_>>();
+
+        let indices = vp_tree.nearest_neighbor_batch_indices(&targets, 4);
+        let graph = vp_tree.knn_graph_parallel(3, 4);
+        assert_eq!(graph, vp_tree.knn_graph(3));
+
+        std::thread::scope(|s| {
+            for (targets, indices) in targets.chunks(50).zip(indices.chunks(50)) {
+                let vp_tree = &vp_tree;
+                s.spawn(move || {
+                    for (target, index) in targets.iter().zip(indices) {
+                        assert_eq!(vp_tree.nearest_neighbor_index(target).map(|index| index as u32), *index);
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn test_lof_neighborhoods() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..300)
+            .map(|_| TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 })
+            .collect();
+        let vp_tree = VpTree::new(points);
+        let items = vp_tree.items();
+
+        let k = 5;
+        let neighborhoods = vp_tree.lof_neighborhoods(k);
+        assert_eq!(neighborhoods.len(), items.len());
+
+        for (index, neighborhood) in neighborhoods.iter().enumerate() {
+            let mut expected = (0..items.len()).filter(|&other| other != index).collect::<Vec<_>>();
+            expected.sort_by(|&a, &b| items[index].distance(&items[a]).total_cmp(&items[index].distance(&items[b])));
+            expected.truncate(k);
+
+            assert_eq!(neighborhood.indices, expected);
+            assert_eq!(neighborhood.distances, expected.iter().map(|&other| items[index].distance(&items[other])).collect::<Vec<_>>());
+            assert_eq!(neighborhood.kth_distance, vp_tree.kth_distance(&items[index], k + 1).unwrap());
+        }
+
+        let single = VpTree::new(vec![TestPoint { x: 0.0, y: 0.0 }]);
+        assert_eq!(single.lof_neighborhoods(3)[0].kth_distance, f64::INFINITY);
+    }
+
+    #[test]
+    fn test_query_single_nearest_matches_nearest_neighbor() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..3000)
+            .map(|_| TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 })
+            .collect();
+        let vp_tree = VpTree::new(points.clone());
+
+        for _ in 0..100 {
+            let target = TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 };
+            let nearest = vp_tree.nearest_neighbor(&target).unwrap();
+            assert_eq!(vp_tree.query(&target, Query::k_nearest_neighbors(1)), vec![nearest]);
+            assert_eq!(vp_tree.query(&target, Query::k_nearest_neighbors(1).sorted()), baseline_linear_search(&points, &target, 1));
+
+            let target = &points[fastrand::usize(..points.len())];
+            let exclusive = vp_tree.nearest_neighbor_exclusive(target).unwrap();
+            assert_eq!(vp_tree.query(target, Query::k_nearest_neighbors(1).exclusive()), vec![exclusive]);
+            assert_eq!(vp_tree.query(target, Query::k_nearest_neighbors(1)), vec![target]);
+        }
+
+        let empty: VpTree<TestPoint> = VpTree::new(Vec::new());
+        assert!(empty.query(&points[0], Query::k_nearest_neighbors(1)).is_empty());
+    }
+
+    #[test]
+    fn test_query_boundary_epsilon() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                (self.x - other.x).abs()
+            }
+        }
+
+        // Overestimates the distance to the vantage point by a rounding error
+        struct RoundedTarget {
+            x: f64,
+        }
+        impl Distance<TestPoint> for RoundedTarget {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                let distance = (self.x - other.x).abs();
+                if other.x == 0.0 { distance + 1e-9 } else { distance }
+            }
+        }
+
+        // Vantage point at 0.0 with threshold 3.0, the item at 3.0 on the left and the item at 10.0 on the right
+        let items = vec![TestPoint { x: 0.0 }, TestPoint { x: 3.0 }, TestPoint { x: 10.0 }];
+        let vp_tree = VpTree::from_parts(items, vec![3.0, 0.0, 0.0]);
+        let target = RoundedTarget { x: 4.0 };
+
+        // The left subtree is exactly on the pruning boundary of the radius
+        assert!(vp_tree.query(&target, Query::neighbors_within_radius(1.0)).is_empty());
+        assert_eq!(vp_tree.query(&target, Query::neighbors_within_radius(1.0).boundary_epsilon(1e-6)), vec![&TestPoint { x: 3.0 }]);
+
+        // The epsilon only widens the search, the results are unchanged
+        let points: Vec<TestPoint> = (0..2000).map(|_| TestPoint { x: fastrand::f64() * 100.0 }).collect();
+        let vp_tree = VpTree::new(points.clone());
+        for _ in 0..50 {
+            let target = TestPoint { x: fastrand::f64() * 100.0 };
+            let expected = baseline_linear_search(&points, &target, 5);
+            assert_eq!(vp_tree.query(&target, Query::k_nearest_neighbors(5).boundary_epsilon(0.5).sorted()), expected);
+            assert_eq!(vp_tree.query(&target, Query::k_nearest_neighbors(1).boundary_epsilon(0.5)), vec![expected[0]]);
+        }
+    }
+
+    #[test]
+    fn test_downsample_grid() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..5000)
+            .map(|_| TestPoint { x: fastrand::f64() * 100.0 - 50.0, y: fastrand::f64() * 100.0 - 50.0 })
+            .collect();
+        let vp_tree = VpTree::new(points.clone());
+
+        let cell_size = 10.0;
+        let cell = |p: &TestPoint| ((p.x / cell_size).floor() as i64, (p.y / cell_size).floor() as i64);
+        let center = |(x, y): (i64, i64)| TestPoint { x: (x as f64 + 0.5) * cell_size, y: (y as f64 + 0.5) * cell_size };
+
+        let sampled = vp_tree.downsample_grid(cell_size, |p| [p.x, p.y]);
+        assert_eq!(sampled.len(), 100);
+
+        let mut cells = std::collections::HashSet::new();
+        for representative in sampled {
+            let representative_cell = cell(representative);
+            assert!(cells.insert(representative_cell));
+
+            let center = center(representative_cell);
+            let closest = points.iter()
+                .filter(|p| cell(p) == representative_cell)
+                .map(|p| p.distance(&center))
+                .fold(f64::INFINITY, f64::min);
+            assert_eq!(representative.distance(&center), closest);
+        }
+    }
+
+    #[test]
+    fn test_closest_pair() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        for _ in 0..10 {
+            let points: Vec<TestPoint> = (0..300)
+                .map(|_| TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 })
+                .collect();
+            let vp_tree = VpTree::new(points);
+            let items = vp_tree.items();
+
+            let mut expected = f64::INFINITY;
+            for i in 0..items.len() {
+                for j in i + 1..items.len() {
+                    expected = expected.min(items[i].distance(&items[j]));
+                }
+            }
+
+            let (i, j, distance) = vp_tree.closest_pair().unwrap();
+            assert!(i < j);
+            assert_eq!(distance, expected);
+            assert_eq!(items[i].distance(&items[j]), expected);
+        }
+
+        let mut vp_tree = VpTree::new(vec![TestPoint { x: 1.0, y: 1.0 }]);
+        assert_eq!(vp_tree.closest_pair(), None);
+        vp_tree.insert(TestPoint { x: 1.0, y: 1.0 });
+        assert_eq!(vp_tree.closest_pair(), Some((0, 1, 0.0)));
+    }
+
+    #[test]
+    fn test_from_chunks() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..5000)
+            .map(|_| TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 })
+            .collect();
+
+        let chunked = VpTree::from_chunks(points.chunks(700).map(|chunk| chunk.to_vec()));
+        let single = VpTree::new(points.clone());
+        assert_eq!(chunked.items().len(), points.len());
+        assert_eq!(chunked.depth(), chunked.expected_depth());
+
+        for _ in 0..50 {
+            let target = TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 };
+            let query = Query::k_nearest_neighbors(10).sorted();
+            assert_eq!(chunked.query(&target, &query), single.query(&target, &query));
+            assert_eq!(chunked.query(&target, &query), baseline_linear_search(&points, &target, 10));
+        }
+
+        let empty = VpTree::<TestPoint>::from_chunks(std::iter::empty());
+        assert!(empty.items().is_empty());
+    }
+
+    #[test]
+    fn test_tree_stats() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..1023)
+            .map(|_| TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 })
+            .collect();
+        let mut vp_tree = VpTree::new(points);
+
+        // 1023 items form a perfect tree with 512 leaves at depth 10
+        let balanced = vp_tree.stats();
+        assert_eq!(balanced.len, 1023);
+        assert_eq!(balanced.depth, 10);
+        assert_eq!(balanced.expected_depth, 10);
+        assert_eq!(balanced.imbalance_ratio, 1.0);
+        assert_eq!(balanced.leaf_count, 512);
+        assert_eq!(balanced.avg_leaf_depth, 10.0);
+        assert_eq!(balanced.root_threshold, Some(vp_tree.node_threshold(vp_tree.root_node().unwrap())));
+
+        for _ in 0..200 {
+            vp_tree.insert(TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 });
+        }
+        let degenerate = vp_tree.stats();
+        assert_eq!(degenerate.len, 1223);
+        assert_eq!(degenerate.depth, 210);
+        assert!(degenerate.imbalance_ratio > 10.0);
+        assert_eq!(degenerate.leaf_count, 512);
+
+        vp_tree.rebuild();
+        assert_eq!(vp_tree.stats().imbalance_ratio, 1.0);
+
+        let empty = VpTree::<TestPoint>::new(Vec::new()).stats();
+        assert_eq!(empty.leaf_count, 0);
+        assert_eq!(empty.avg_leaf_depth, 0.0);
+        assert_eq!(empty.root_threshold, None);
+    }
+
+    #[test]
+    fn test_query_batch_locality_aware() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..5000)
+            .map(|_| TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 })
+            .collect();
+        let mut vp_tree = VpTree::new(points);
+        vp_tree.insert(TestPoint { x: 50.0, y: 50.0 });
+
+        // Targets along a spiral, consecutive targets are close
+        let targets = (0..500)
+            .map(|i| {
+                let t = i as f64 * 0.05;
+                TestPoint { x: 50.0 + t * 1.5 * t.cos(), y: 50.0 + t * 1.5 * t.sin() }
+            })
+            .collect::<Vec<_>>();
+
+        for query in [
+            Query::k_nearest_neighbors(8).sorted(),
+            Query::k_nearest_neighbors(1),
+            Query::k_nearest_neighbors_within_radius(5, 2.0).exclusive().sorted(),
+            Query::neighbors_within_radius(3.0).sorted(),
+        ] {
+            let results = vp_tree.query_batch_locality_aware(&targets, &query);
+            assert_eq!(results.len(), targets.len());
+            for (target, result) in targets.iter().zip(results) {
+                // Sorting only changes the unsorted queries
+                let mut result = result;
+                let mut expected = vp_tree.query(target, &query);
+                result.sort_by(|a, b| target.distance(*a).total_cmp(&target.distance(*b)));
+                expected.sort_by(|a, b| target.distance(*a).total_cmp(&target.distance(*b)));
+                assert_eq!(result, expected);
+            }
+        }
+
+        // Repeated targets carry a bound equal to the distance of the farthest previous result, which must still be found
+        let line = VpTree::new((0..10).map(|i| TestPoint { x: i as f64, y: 0.0 }).collect());
+        let query = Query::k_nearest_neighbors(3).within_radius(100.0).exclusive_boundary().sorted();
+        let targets = [TestPoint { x: 0.0, y: 0.0 }, TestPoint { x: 0.0, y: 0.0 }];
+        for result in line.query_batch_locality_aware(&targets, &query) {
+            assert_eq!(result, line.query(&targets[0], &query));
+            assert_eq!(result.len(), 3);
+        }
+    }
+
+    #[test]
+    fn test_prefetch() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            value: f64,
+        }
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                CALLS.fetch_add(1, Ordering::Relaxed);
+                (self.value - other.value).abs()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..10_000)
+            .map(|i| TestPoint { value: i as f64 })
+            .collect();
+        let vp_tree = VpTree::new(points);
+        let target = TestPoint { value: 4321.4 };
+        let query = Query::k_nearest_neighbors(5).sorted();
+
+        let before = vp_tree.query(&target, &query);
+        let calls = CALLS.load(Ordering::Relaxed);
+        vp_tree.prefetch();
+        assert_eq!(CALLS.load(Ordering::Relaxed), calls);
+        assert_eq!(vp_tree.query(&target, &query), before);
+
+        let empty: VpTree<TestPoint> = VpTree::new(vec![]);
+        empty.prefetch();
+    }
+
+    #[test]
+    fn test_connected_components() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        fastrand::seed(86);
+        let centers = [(0.0, 0.0), (100.0, 0.0), (0.0, 100.0)];
+        let mut points: Vec<TestPoint> = (0..300)
+            .map(|i| {
+                let (x, y) = centers[i % 3];
+                TestPoint { x: x + fastrand::f64() * 10.0, y: y + fastrand::f64() * 10.0 }
+            })
+            .collect();
+        points.push(TestPoint { x: 50.0, y: 50.0 });
+        let vp_tree = VpTree::new(points);
+        let items = vp_tree.items();
+        let radius = 3.0;
+
+        let mut labels: Vec<usize> = (0..items.len()).collect();
+        loop {
+            let mut changed = false;
+            for a in 0..items.len() {
+                for b in 0..items.len() {
+                    if items[a].distance(&items[b]) <= radius && labels[b] < labels[a] {
+                        labels[a] = labels[b];
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        let mut expected: Vec<Vec<usize>> = Vec::new();
+        for index in 0..items.len() {
+            match expected.iter_mut().find(|component| labels[component[0]] == labels[index]) {
+                Some(component) => component.push(index),
+                None => expected.push(vec![index]),
+            }
+        }
+
+        let components = vp_tree.connected_components(radius);
+        assert_eq!(components, expected);
+        assert_eq!(components.len(), 4);
+        assert!(components.iter().any(|component| component.len() == 1 && items[component[0]] == TestPoint { x: 50.0, y: 50.0 }));
+
+        assert_eq!(vp_tree.connected_components(1000.0), vec![(0..items.len()).collect::<Vec<_>>()]);
+
+        let empty: VpTree<TestPoint> = VpTree::new(vec![]);
+        assert!(empty.connected_components(1.0).is_empty());
+    }
+
+    #[test]
+    fn test_query_columns() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..2000)
+            .map(|_| TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 })
+            .collect();
+        let vp_tree = VpTree::new(points.clone());
+
+        for _ in 0..20 {
+            let target = TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 };
+
+            let (indices, distances) = vp_tree.query_columns(&target, Query::neighbors_within_radius(8.0));
+            let zipped: Vec<(&TestPoint, f64)> = indices.iter().map(|&index| &vp_tree.items()[index]).zip(distances).collect();
+            assert_eq!(zipped, vp_tree.within_radius_sorted_with_distances(&target, 8.0));
+
+            let (indices, distances) = vp_tree.query_columns(&target, Query::k_nearest_neighbors(10));
+            let items: Vec<&TestPoint> = indices.iter().map(|&index| &vp_tree.items()[index]).collect();
+            assert_eq!(items, baseline_linear_search(&points, &target, 10));
+            assert!(distances.is_sorted());
+            assert_eq!(distances, items.iter().map(|item| item.distance(&target)).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_nearest_neighbor_hinted() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                CALLS.fetch_add(1, Ordering::Relaxed);
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..5000)
+            .map(|_| TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0 })
+            .collect();
+        let vp_tree = VpTree::new(points);
+
+        let mut hint = 0;
+        for i in 0..500 {
+            let t = i as f64 / 500.0 * std::f64::consts::TAU;
+            let target = TestPoint { x: 500.0 + t.sin() * 400.0, y: 500.0 + (2.0 * t).cos() * 400.0 };
+
+            let (index, distance) = vp_tree.nearest_neighbor_hinted(&target, hint).unwrap();
+            assert_eq!(Some(index), vp_tree.nearest_neighbor_index(&target));
+            assert_eq!(distance, vp_tree.items()[index].distance(&target));
+
+            let stale = vp_tree.nearest_neighbor_hinted(&target, fastrand::usize(..vp_tree.items().len())).unwrap();
+            assert_eq!(stale, (index, distance));
+            hint = index;
+        }
+
+        let target = vp_tree.items()[42].clone();
+        assert_eq!(vp_tree.nearest_neighbor_hinted(&target, 7), Some((42, 0.0)));
+
+        // The distance to the hint is counted like every other distance of the search
+        #[cfg(feature = "instrument")]
+        {
+            vp_tree.reset_counters();
+            let calls = CALLS.load(Ordering::Relaxed);
+            vp_tree.nearest_neighbor_hinted(&target, 7);
+            vp_tree.nearest_neighbor_hinted(&target, 42);
+            assert_eq!(vp_tree.distance_call_count() as usize, CALLS.load(Ordering::Relaxed) - calls);
+        }
+    }
+
+    #[test]
+    fn test_map_payload_parallel() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint<P> {
+            x: f64,
+            y: f64,
+            payload: P,
+        }
+        impl<P> Distance<TestPoint<P>> for TestPoint<P> {
+            fn distance(&self, other: &TestPoint<P>) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint<u32>> = (0..10_000)
+            .map(|i| TestPoint { x: fastrand::f64() * 1000.0, y: fastrand::f64() * 1000.0, payload: i })
+            .collect();
+        let vp_tree = VpTree::new(points);
+        let decode = |point: TestPoint<u32>| TestPoint { x: point.x, y: point.y, payload: format!("item {}", point.payload) };
+
+        let serial = vp_tree.clone().map_payload(decode);
+        for threads in [1, 3, 8] {
+            let parallel = vp_tree.clone().map_payload_parallel(decode, threads);
+            assert_eq!(parallel.items(), serial.items());
+
+            let target = TestPoint { x: 500.0, y: 500.0, payload: String::new() };
+            let query = Query::k_nearest_neighbors(10).sorted();
+            assert_eq!(parallel.query(&target, &query), serial.query(&target, &query));
+        }
+
+        let empty: VpTree<TestPoint<u32>> = VpTree::new(vec![]);
+        assert!(empty.map_payload_parallel(decode, 4).items().is_empty());
+    }
+
+    #[test]
+    fn test_from_flat() {
+        use vp_tree::metrics::{FlatPoint, Metric};
+
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            cords: [f64; 3],
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                self.cords.iter().zip(other.cords.iter())
+                    .map(|(a, b)| (a - b).abs())
+                    .sum()
+            }
+        }
+
+        let coords: Vec<f64> = (0..3000).map(|_| fastrand::f64() * 100.0).collect();
+        let points: Vec<TestPoint> = coords.chunks_exact(3)
+            .map(|row| TestPoint { cords: row.try_into().unwrap() })
+            .collect();
+        let struct_tree = VpTree::new(points);
+        let coords_copy = coords.clone();
+        let flat_tree = VpTree::from_flat(coords, 3, Metric::Manhattan);
+        assert_eq!(flat_tree.items().len(), 1000);
+
+        for _ in 0..20 {
+            let target = [(); 3].map(|_| fastrand::f64() * 100.0);
+            let query = Query::k_nearest_neighbors(10).sorted();
+            let expected: Vec<&[f64]> = struct_tree.query(&TestPoint { cords: target }, &query)
+                .into_iter()
+                .map(|point| &point.cords[..])
+                .collect();
+            let result: Vec<&[f64]> = flat_tree.query(&&target[..], &query)
+                .into_iter()
+                .map(|point: &FlatPoint| point.coords())
+                .collect();
+            assert_eq!(result, expected);
+        }
+
+        // Rows refer to their position in the buffer
+        for point in flat_tree.items() {
+            assert_eq!(point.coords(), &coords_copy[point.row() * 3..point.row() * 3 + 3]);
+            assert_eq!(point.metric(), Metric::Manhattan);
+        }
+
+        let euclidean = VpTree::from_flat(vec![0.0, 0.0, 3.0, 4.0], 2, Metric::Euclidean);
+        let target: &[f64] = &[0.0, 0.0];
+        assert_eq!(euclidean.items()[0].distance(&euclidean.items()[1]), 5.0);
+        assert_eq!(euclidean.query(&target, Query::neighbors_within_radius(5.0)).len(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "compact-thresholds")]
+    fn test_compact_thresholds() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            value: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                (self.value - other.value).abs()
+            }
+        }
+
+        // Two narrow clusters far apart, distances between the clusters differ by less than the precision of f32
+        let random_point = || TestPoint { value: fastrand::u32(0..2) as f64 * 1e9 + fastrand::f64() * 100.0 };
+        let points: Vec<TestPoint> = (0..20_000).map(|_| random_point()).collect();
+        let mut vp_tree = VpTree::new(points.clone());
+        let expected_tree = vp_tree.clone();
+        vp_tree.compact_thresholds();
+        assert_eq!(vp_tree.capacity().1, points.len());
+
+        let check = |vp_tree: &VpTree<TestPoint>| {
+            for _ in 0..50 {
+                let target = random_point();
+                let query = Query::k_nearest_neighbors(10).sorted();
+                assert_eq!(vp_tree.query(&target, &query), baseline_linear_search(&points, &target, 10));
+
+                let radius = fastrand::f64() * 50.0;
+                let mut within = vp_tree.query(&target, Query::neighbors_within_radius(radius));
+                let mut expected = expected_tree.query(&target, Query::neighbors_within_radius(radius));
+                within.sort_by(|a, b| a.value.total_cmp(&b.value));
+                expected.sort_by(|a, b| a.value.total_cmp(&b.value));
+                assert_eq!(within, expected);
+            }
+        };
+        check(&vp_tree);
+
+        let root = vp_tree.root_node().unwrap();
+        let (items, thresholds) = vp_tree.clone().into_parts();
+        assert_eq!(thresholds.len(), points.len());
+        check(&VpTree::from_parts(items, thresholds));
+
+        vp_tree.rebuild_subtree(vp_tree.left_child(root).unwrap());
+        check(&vp_tree);
+
+        vp_tree.compact_thresholds();
+        vp_tree.rebuild();
+        assert_eq!(vp_tree.capacity().1, points.len());
+        check(&vp_tree);
+    }
+
+    #[test]
+    fn test_query_each() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let mut points: Vec<TestPoint> = (0..3000)
+            .map(|_| TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 })
+            .collect();
+        points.push(TestPoint { x: 50.0, y: 50.0 });
+        let vp_tree = VpTree::new(points);
+        let target = TestPoint { x: 50.0, y: 50.0 };
+
+        let queries = [
+            (Query::k_nearest_neighbors(1), false),
+            (Query::k_nearest_neighbors(10).sorted(), true),
+            (Query::k_nearest_neighbors(10).exclusive().sorted(), true),
+            (Query::k_nearest_neighbors_within_radius(50, 3.0), false),
+            (Query::neighbors_within_radius(8.0), false),
+            (Query::neighbors_within_radius(8.0).sorted(), true),
+            (Query::neighbors_within_radius(8.0).exclusive().exclusive_boundary(), false),
+        ];
+        let key = |p: &&TestPoint| (p.x.to_bits(), p.y.to_bits());
+        for (query, sorted) in &queries {
+            let mut results = Vec::new();
+            vp_tree.query_each(&target, query, |item, distance| {
+                assert_eq!(distance, item.distance(&target));
+                results.push(item);
+            });
+
+            let mut expected = vp_tree.query(&target, query);
+            if !sorted {
+                results.sort_by_key(key);
+                expected.sort_by_key(key);
+            }
+            assert_eq!(results, expected);
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_deprecated_querry_spelling() {
+        use vp_tree::Querry;
+
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..1000)
+            .map(|_| TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 })
+            .collect();
+        let target = TestPoint { x: 50.0, y: 50.0 };
+        let old: Querry = Querry::k_nearest_neighbors(10).sorted();
+        let new = Query::k_nearest_neighbors(10).sorted();
+
+        let vp_tree = VpTree::new(points);
+        assert_eq!(vp_tree.querry(&target, &old), vp_tree.query(&target, &new));
+    }
+
+    #[test]
+    fn test_query_with_distances() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..2000)
+            .map(|_| TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 })
+            .collect();
+        let vp_tree = VpTree::new(points.clone());
+
+        for _ in 0..20 {
+            let target = TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 };
+            for query in [Query::k_nearest_neighbors(10).sorted(), Query::neighbors_within_radius(5.0), Query::k_nearest_neighbors(1)] {
+                let with_distances = vp_tree.query_with_distances(&target, &query);
+                let items: Vec<&TestPoint> = with_distances.iter().map(|&(item, _)| item).collect();
+                assert_eq!(items, vp_tree.query(&target, &query));
+                assert!(with_distances.iter().all(|&(item, distance)| distance == item.distance(&target)));
+            }
+
+            let sorted = vp_tree.query_with_distances(&target, Query::k_nearest_neighbors(10).sorted());
+            assert!(sorted.is_sorted_by(|a, b| a.1 <= b.1));
+            assert_eq!(sorted.iter().map(|&(item, _)| item).collect::<Vec<_>>(), baseline_linear_search(&points, &target, 10));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde_round_trip() {
+        use vp_tree::RebuildPolicy;
+
+        #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct TestPoint {
+            x: f64,
+            y: f64,
+        }
+        impl Distance<TestPoint> for TestPoint {
+            fn distance(&self, other: &TestPoint) -> f64 {
+                ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+            }
+        }
+
+        let points: Vec<TestPoint> = (0..2000)
+            .map(|_| TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 })
+            .collect();
+        let mut vp_tree = VpTree::new(points);
+        vp_tree.set_rebuild_policy(RebuildPolicy::AfterMutations(100));
+        vp_tree.insert(TestPoint { x: 50.0, y: 50.0 });
+
+        let json = serde_json::to_string(&vp_tree).unwrap();
+        let from_json: VpTree<TestPoint> = serde_json::from_str(&json).unwrap();
+
+        let config = bincode::config::standard();
+        let bytes = bincode::serde::encode_to_vec(&vp_tree, config).unwrap();
+        let (from_bincode, _): (VpTree<TestPoint>, usize) = bincode::serde::decode_from_slice(&bytes, config).unwrap();
+
+        for restored in [&from_json, &from_bincode] {
+            assert_eq!(restored.clone().into_parts(), vp_tree.clone().into_parts());
+            assert_eq!(restored.items(), vp_tree.items());
+            for _ in 0..20 {
+                let target = TestPoint { x: fastrand::f64() * 100.0, y: fastrand::f64() * 100.0 };
+                let query = Query::k_nearest_neighbors(10).sorted();
+                assert_eq!(restored.query(&target, &query), vp_tree.query(&target, &query));
+            }
+        }
+
+        // Thresholds without items to refer to are rejected instead of causing out of bounds indexing in later queries
+        let invalid = r#"{"items":[{"x":0.0,"y":0.0}],"nodes":[0.0,1.0,2.0],"rebuild_policy":"Never","selection":"Random"}"#;
+        assert!(serde_json::from_str::<VpTree<TestPoint>>(invalid).is_err());
+        let valid = r#"{"items":[{"x":0.0,"y":0.0}],"nodes":[0.0],"rebuild_policy":"Never","selection":"Random"}"#;
+        assert_eq!(serde_json::from_str::<VpTree<TestPoint>>(valid).unwrap().items().len(), 1);
+        #[cfg(feature = "compact-thresholds")]
+        {
+            let mixed = r#"{"items":[{"x":0.0,"y":0.0}],"nodes":[0.0],"compact_nodes":[0.0],"rebuild_policy":"Never","selection":"Random"}"#;
+            assert!(serde_json::from_str::<VpTree<TestPoint>>(mixed).is_err());
+        }
+    }
+
     fn baseline_linear_search<'a, T, U>(data: &'a [T], target: &U, k: usize) -> Vec<&'a T>
     where
         U: Distance<T>,